@@ -1,50 +1,199 @@
 use std::fs;
 use std::path::PathBuf;
 use std::collections::HashSet;
-use lazy_static::lazy_static;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use chrono::Local;
 
-lazy_static! {
-    static ref LANGS: HashSet<String> = {
-        let mut l = HashSet::new();
-        l.insert("c".to_string());
-        // l.insert("cpp".to_string());
-        l
-    };
+/// Languages that can be searched for, along with the patterns of their files
+/// for a sparse checkout. Names are GitHub's `language:` qualifiers.
+static LANGUAGES: [(&str, &[&str]); 2] = [
+    ("c",   &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.h"]),
+];
+
+/// Return the file patterns of LANG, or None if it isn't supported.
+pub fn language_patterns(lang: &str) -> Option<&'static [&'static str]> {
+    return LANGUAGES.iter()
+        .find(|(name, _)| *name == lang)
+        .map(|(_, patterns)| *patterns);
 }
 
 /// Top level configuration.
-#[derive(Clone, Debug, Deserialize)]
+///
+/// Secrets are left out when it is serialized, so the result can be stored
+/// with the crawl.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     pub miner: Miner,
     pub runner: Runner,
     pub database: Database,
+    #[serde(default)]
+    pub search: Search,
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    pub history: Option<History>,
+    pub submodules: Option<Submodules>,
 }
 
 /// Configuration for the miner.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Miner {
     pub threads: usize,
     pub tries: usize,
+    pub max_processes: Option<usize>,   // Defaults to the number of CPUs
+    pub max_memory: Option<u64>,        // In MB, address space of each process
+    pub max_cpu_time: Option<u64>,      // In seconds, for each process
+    #[serde(default)]
+    pub sandbox: bool,                  // Run each process in a namespace sandbox
 }
 
 /// Configuration for the runner.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Runner {
     pub threads: usize,
+    pub compile_threads: Option<usize>, // Defaults to `threads` × `miner.threads`
     pub min_stars: usize,
     pub languages: HashSet<String>,
+    #[serde(default, skip_serializing)]
     pub github_api_key: String,
+    #[serde(default, skip_serializing)]
+    pub github_api_keys: Vec<String>,
+    pub github_token_file: Option<PathBuf>,
     pub log_dir: PathBuf,
     pub log_level: String,
     pub tmp_dir: PathBuf,
+    pub mirror_dir: Option<PathBuf>,
+    pub mirror_max_size: Option<u64>,   // In MB
+    #[serde(default = "default_partial_clone")]
+    pub partial_clone: bool,            // Only fetch & check out source files
+    pub max_repo_size: Option<u64>,     // In MB
+    pub clone_timeout: Option<u64>,     // In seconds
+    pub max_repo_time: Option<u64>,     // In seconds, files left after are skipped
+    #[serde(default = "default_stall_time")]
+    pub stall_time: u64,                // Seconds without progress before a warning
+    pub min_free_space: Option<u64>,    // In MB, clones pause below this
+    pub worker_id: Option<String>,      // Unique per process, defaults to host & pid
+    #[serde(default = "default_lease_time")]
+    pub lease_time: u64,                // Seconds until an unrenewed claim expires
+    #[serde(default)]
+    pub prefetch: usize,                // Repos cloned ahead of the miners
+    #[serde(default = "default_clone_threads")]
+    pub clone_threads: usize,           // Threads cloning repos when prefetching
+    pub prefetch_max_size: Option<u64>, // In MB, total size of prefetched repos
+    pub metrics_port: Option<u16>,      // Serve /metrics on this local port
+}
+
+fn default_partial_clone() -> bool {
+    return true;
+}
+
+fn default_stall_time() -> u64 {
+    return 900;
+}
+
+fn default_lease_time() -> u64 {
+    return 600;
+}
+
+fn default_clone_threads() -> usize {
+    return 2;
+}
+
+/// Configuration for the repository search.
+///
+/// Most fields map to a GitHub search qualifier. Unset fields are left out of
+/// the query.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Search {
+    pub api_base_url: String,           // For example, a GitHub Enterprise server
+    pub max_retries: usize,             // Retries of a failed request
+
+    pub topics: Vec<String>,            // topic:TOPIC (all must match)
+    pub license: Option<String>,        // license:LICENSE
+    pub pushed_after: Option<String>,   // pushed:>DATE
+    pub created: Option<String>,        // created:RANGE
+    pub size: Option<String>,           // size:RANGE (in KB)
+    pub exclude_archived: bool,         // archived:false
+    pub exclude_forks: bool,            // fork:false
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        return Self {
+            api_base_url: "https://api.github.com".to_string(),
+            max_retries: 5,
+            topics: vec![],
+            license: None,
+            pushed_after: None,
+            created: None,
+            size: None,
+            exclude_archived: false,
+            exclude_forks: false,
+        };
+    }
+}
+
+/// A source of repositories. Defaults to the GitHub search if none are given.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Source {
+    /// Search GitHub using the `[search]` section.
+    Github,
+    /// File of clone URLs, one per line, or as CSV with `url,name,stars`.
+    List { path: PathBuf },
+    /// Directory containing bare or working repositories.
+    Local { path: PathBuf },
+    /// A `.tar.gz` or `.zip` source archive, or a directory of them.
+    Archive { path: PathBuf },
+    /// Projects on a GitLab instance.
+    Gitlab {
+        url: String,
+        #[serde(skip_serializing)]
+        token: Option<String>,
+    },
+    /// Repositories on a Gitea instance.
+    Gitea {
+        url: String,
+        #[serde(skip_serializing)]
+        token: Option<String>,
+    },
+}
+
+/// Configuration for mining multiple revisions of each repository.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct History {
+    pub mode: HistoryMode,
+    #[serde(default = "default_max_revisions")]
+    pub max_revisions: usize,   // Only the most recent revisions are kept
+}
+
+/// Which revisions of a repository to mine.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryMode {
+    Yearly,     // Last commit of each year
+    Tags,       // Every tag
+}
+
+fn default_max_revisions() -> usize {
+    return 20;
+}
+
+/// Configuration for initializing submodules after cloning.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Submodules {
+    #[serde(default)]
+    pub allow: Vec<String>,     // URL prefixes to initialize, or all if empty
+    #[serde(default)]
+    pub mine_sources: bool,     // Mine source files in submodules, not just headers
 }
 
 /// Configuration for the database.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Database {
     pub user: String,
+    #[serde(skip_serializing)]
     pub password: String,
     pub host: String,
     pub database: String,
@@ -60,9 +209,11 @@ pub fn read_config(path: PathBuf) -> Config {
     };
 
     // Ensure that the list of languages is valid
-    let languages = &config.runner.languages;
-    if !languages.is_subset(&LANGS) {
-        panic!("Invalid language: {:?}", languages.difference(&LANGS))
+    let invalid: Vec<_> = config.runner.languages.iter()
+        .filter(|l| language_patterns(l).is_none())
+        .collect();
+    if !invalid.is_empty() {
+        panic!("Invalid language: {:?}", invalid)
     }
 
     // Set the log directory based on the time
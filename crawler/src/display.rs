@@ -0,0 +1,91 @@
+// A block of status lines kept at the bottom of the terminal. Log messages are
+// written above it, so the two don't overwrite each other.
+
+use log::Record;
+use log4rs::append::Append;
+use log4rs::encode::Encode;
+use log4rs::encode::writer::ansi::AnsiWriter;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+
+/// Lines currently drawn at the bottom of the terminal.
+static SCREEN: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Return true if stderr is a terminal the status can be drawn on.
+pub fn is_terminal() -> bool {
+    return io::stderr().is_terminal();
+}
+
+/// Replace the status block with LINES. Lines are cut to the width of the
+/// terminal, as wrapped lines couldn't be erased.
+pub fn draw(lines: Vec<String>) {
+    let width = width();
+    let lines: Vec<String> = lines.into_iter()
+        .map(|l| l.chars().take(width).collect())
+        .collect();
+
+    let mut screen = SCREEN.lock().unwrap();
+    let mut stderr = io::stderr().lock();
+    erase(&mut stderr, &screen);
+    write_lines(&mut stderr, &lines);
+    *screen = lines;
+}
+
+/// Remove the status block.
+pub fn clear() {
+    draw(vec![]);
+}
+
+/// Return the width of the terminal, less one column.
+fn width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    match ret {
+        0 if size.ws_col > 1 => size.ws_col as usize - 1,
+        _ => 79,
+    }
+}
+
+/// Move the cursor up over LINES & clear them.
+fn erase(out: &mut impl Write, lines: &[String]) {
+    if !lines.is_empty() {
+        let _ = write!(out, "\x1b[{}F\x1b[J", lines.len());
+    }
+}
+
+fn write_lines(out: &mut impl Write, lines: &[String]) {
+    for line in lines {
+        let _ = writeln!(out, "{}", line);
+    }
+    let _ = out.flush();
+}
+
+/// Log appender that writes to stderr above the status block.
+#[derive(Debug)]
+pub struct Appender {
+    encoder: Box<dyn Encode>,
+}
+
+impl Appender {
+    pub fn new(encoder: Box<dyn Encode>) -> Self {
+        return Self { encoder };
+    }
+}
+
+impl Append for Appender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let mut message = AnsiWriter(vec![]);
+        self.encoder.encode(&mut message, record)?;
+
+        let screen = SCREEN.lock().unwrap();
+        let mut stderr = io::stderr().lock();
+        erase(&mut stderr, &screen);
+        stderr.write_all(&message.0)?;
+        write_lines(&mut stderr, &screen);
+        return Ok(());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
@@ -39,12 +39,13 @@ pub struct CompileInput<'a> {
     pub root: &'a PathBuf,
     pub file: &'a PathBuf,
     pub content: &'a str,           // File after preprocessing
-    pub headers: &'a Vec<PathBuf>   // Header choices
+    pub headers: &'a Vec<PathBuf>,  // Header choices
+    pub submodule: Option<&'a PathBuf>, // Submodule of the file, if not the host repo
 }
 
 pub struct CompileResult {
     pub data: Result<MatchData, ()>,    // Instance specific match data
-    pub to_log: String,                 // Data to output to the current repositories log
+    pub to_log: String,                 // Notes added to the attempt's log record
 }
 
 // Intern:
@@ -53,16 +54,38 @@ pub struct CompileResult {
 #[derive(Clone)]
 pub struct InternInput<'a> {
     pub config: &'a Config,
+    pub run_id: i64,                // Row of the `runs` table of this crawl
     pub repo_id: i64,
+    pub commit: Option<&'a str>,    // Commit that was mined, if known
     pub data: &'a Vec<MatchData>,
     pub db: &'a db::Database,
 }
 
 pub type InternResult = Result<(), ()>;
 
+// Provenance:
+
+pub struct Provenance {
+    pub name: String,
+    pub version: String,
+    pub tools: Vec<PathBuf>,    // Binaries whose `--version` output is recorded
+    pub plugins: Vec<PathBuf>,  // Pass plugins whose hash is recorded
+}
+
 pub type AnyInterface = Arc<dyn Interface + Sync + Send + RefUnwindSafe>;
 
 pub trait Interface {
+    /// Describe the interface & the external tools it runs, which is recorded
+    /// along with each crawl. By default, nothing is known about it.
+    fn provenance(&self) -> Provenance {
+        return Provenance {
+            name: "unknown".to_string(),
+            version: "unknown".to_string(),
+            tools: vec![],
+            plugins: vec![],
+        };
+    }
+
     /// Called once after the search has finished but before any preprocessing /
     /// compilation happens. Does nothing by default.
     fn init(&self, _input: InitInput) -> InitResult {
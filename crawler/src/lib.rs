@@ -1,9 +1,13 @@
 #[allow(dead_code)]
 
 mod config;
+mod display;
 pub mod interface;
+mod metrics;
 mod miner;
+pub mod process;
 mod runner;
+mod sandbox;
 
 use config as crawler_config;
 
@@ -12,7 +16,7 @@ use interface::AnyInterface;
 use std::path::PathBuf;
 use log;
 use log4rs::config::{Appender, Config, Root};
-use log4rs::append::{console::{ConsoleAppender, Target}, file::FileAppender};
+use log4rs::append::{Append, console::{ConsoleAppender, Target}, file::FileAppender};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs;
 
@@ -29,7 +33,7 @@ fn cli() -> Command {
         .arg_required_else_help(true)
         .subcommand(
             Command::new("mine")
-                .about("Only mine a given repository")
+                .about("Only mine a given repository or source archive")
                 .arg_required_else_help(true)
                 .arg(arg!(path: <PATH>)
                      .value_parser(clap::value_parser!(PathBuf)))
@@ -47,6 +51,24 @@ fn cli() -> Command {
         .subcommand(
             Command::new("search")
                 .about("Only search for repositories")
+                .arg(arg!(--resume "Continue from where the last search stopped"))
+                .arg(arg!(--refresh "Search finished windows again")
+                     .conflicts_with("resume"))
+        )
+        // Summarize the results in the database
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("report")
+                .about("Summarize the results of previous crawls")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("failures")
+                        .about("Show why files failed to compile")
+                        .arg(arg!(--limit <N> "Number of headers & identifiers to show")
+                             .value_parser(clap::value_parser!(usize))
+                             .default_value("20"))
+                )
         )
 }
 
@@ -75,11 +97,14 @@ fn setup_logging(config: &crawler_config::Config) -> log4rs::Handle {
         "{d(%Y-%m-%d %H:%M:%S)} {h({l}): <5} {T: <9} {({t}:{L}): <30} - {m}{n}"
     ));
 
-    // Log to stderr
-    let stderr = ConsoleAppender::builder()
-        .target(Target::Stderr)
-        .encoder(pattern.clone())
-        .build();
+    // Log to stderr, above the status display when it is a terminal
+    let stderr: Box<dyn Append> = match display::is_terminal() {
+        true => Box::new(display::Appender::new(pattern.clone())),
+        false => Box::new(ConsoleAppender::builder()
+            .target(Target::Stderr)
+            .encoder(pattern.clone())
+            .build()),
+    };
 
 
     // Log to a file
@@ -92,7 +117,7 @@ fn setup_logging(config: &crawler_config::Config) -> log4rs::Handle {
     // Configure
     let log_conf = Config::builder()
         .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .appender(Appender::builder().build("stderr", Box::new(stderr)))
+        .appender(Appender::builder().build("stderr", stderr))
         .build(
             Root::builder()
                 .appender("logfile")
@@ -118,16 +143,36 @@ pub fn run(interface: AnyInterface) {
     // Setup logging
     let _handle = setup_logging(&config);
 
+    // Limit the processes started by the interfaces
+    process::configure(&config.miner);
+
     match matches.subcommand() {
         Some(("mine", sub)) => {
             let path = get_path(sub, "path");
-            miner::mine_one(path, config, interface);
+            runner::mine_one(path, config, interface);
         },
         Some(("crawl", _sub)) => {
+            // Serve metrics for monitoring, if enabled
+            metrics::serve(&config.runner);
             runner::crawl(&config, interface);
         },
-        Some(("search", _sub)) => {
-            runner::search(&config);
+        Some(("search", sub)) => {
+            let mode = if sub.get_flag("resume") {
+                runner::SearchMode::Resume
+            } else if sub.get_flag("refresh") {
+                runner::SearchMode::Refresh
+            } else {
+                runner::SearchMode::Fresh
+            };
+            metrics::serve(&config.runner);
+            runner::search(&config, mode);
+        },
+        Some(("report", sub)) => match sub.subcommand() {
+            Some(("failures", sub)) => {
+                let limit = *sub.get_one::<usize>("limit").expect("default");
+                runner::report_failures(&config, limit);
+            },
+            _ => unreachable!(),
         },
         _ => unreachable!(),
     }
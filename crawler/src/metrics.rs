@@ -0,0 +1,240 @@
+// Counters for monitoring a crawl, served in the Prometheus text format.
+
+use crate::config::Runner;
+
+use log::{debug, error, info};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub static REPOS_CLONED: Counter = Counter::new(
+    "crawler_repos_cloned_total", "Repositories cloned or extracted");
+pub static REPOS_MINED: Counter = Counter::new(
+    "crawler_repos_mined_total", "Repositories mined");
+pub static REPOS_FAILED: Counter = Counter::new(
+    "crawler_repos_failed_total", "Repositories that failed to be cloned or mined");
+pub static FILES_COMPILED: Counter = Counter::new(
+    "crawler_files_compiled_total", "Source files that compiled");
+pub static FILES_FAILED: Counter = Counter::new(
+    "crawler_files_failed_total", "Source files that failed to compile");
+pub static COMPILE_ATTEMPTS: Counter = Counter::new(
+    "crawler_compile_attempts_total", "Header combinations tried");
+pub static TIMEOUTS: Counter = Counter::new(
+    "crawler_subprocess_timeouts_total", "External processes that were killed for taking too long");
+pub static INTERN_SECONDS: Summary = Summary::new(
+    "crawler_intern_seconds", "Time taken to intern the results of a revision");
+pub static RATE_LIMIT_REMAINING: Gauge = Gauge::new(
+    "crawler_github_rate_limit_remaining", "GitHub API requests left, over every token");
+
+/// A value that only increases.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        return Self { name, help, value: AtomicU64::new(0) };
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        out.push_str(&format!("{} {}\n", self.name, self.value.load(Ordering::Relaxed)));
+    }
+}
+
+/// A value that can go up & down. Isn't shown until it has been set.
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        return Self { name, help, value: AtomicI64::new(i64::MIN) };
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let value = self.value.load(Ordering::Relaxed);
+        if value == i64::MIN {
+            return;
+        }
+        header(out, self.name, self.help, "gauge");
+        out.push_str(&format!("{} {}\n", self.name, value));
+    }
+}
+
+/// The total & number of observed durations.
+pub struct Summary {
+    name: &'static str,
+    help: &'static str,
+    micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Summary {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        return Self { name, help, micros: AtomicU64::new(0), count: AtomicU64::new(0) };
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        self.micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let sum = self.micros.load(Ordering::Relaxed) as f64 / 1e6;
+        header(out, self.name, self.help, "summary");
+        out.push_str(&format!("{}_sum {}\n", self.name, sum));
+        out.push_str(&format!("{}_count {}\n", self.name, self.count.load(Ordering::Relaxed)));
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    out.push_str(&format!("# HELP {} {}\n", name, help));
+    out.push_str(&format!("# TYPE {} {}\n", name, kind));
+}
+
+/// Return every metric in the Prometheus text format.
+fn render() -> String {
+    let mut out = String::new();
+    for counter in [
+        &REPOS_CLONED, &REPOS_MINED, &REPOS_FAILED,
+        &FILES_COMPILED, &FILES_FAILED, &COMPILE_ATTEMPTS, &TIMEOUTS,
+    ] {
+        counter.render(&mut out);
+    }
+    INTERN_SECONDS.render(&mut out);
+    RATE_LIMIT_REMAINING.render(&mut out);
+    return out;
+}
+
+/// Serve `/metrics` on the configured port of localhost, if any. The crawl
+/// continues without metrics if the port can't be used.
+pub fn serve(config: &Runner) {
+    let Some(port) = config.metrics_port else {
+        return;
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Failed to serve metrics on port {}: {}", port, e);
+            return;
+        },
+    };
+    info!("Serving metrics on http://127.0.0.1:{}/metrics", port);
+
+    let spawned = thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(s) => respond(s),
+                    Err(e) => debug!("Failed to accept metrics connection: {}", e),
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start metrics thread: {}", e);
+    }
+}
+
+/// Answer a single HTTP request.
+fn respond(stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let mut reader = BufReader::new(&stream);
+
+    // Only the request line is needed, but the headers are read so the
+    // client doesn't see the connection reset
+    let mut request = String::new();
+    if reader.read_line(&mut request).is_err() {
+        return;
+    }
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body
+            )
+        },
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    let mut stream = &stream;
+    let _ = stream.write_all(response.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counter() {
+        let counter = Counter::new("test_total", "Things counted");
+        counter.inc();
+        counter.inc();
+
+        let mut out = String::new();
+        counter.render(&mut out);
+        assert_eq!(out, "# HELP test_total Things counted\n# TYPE test_total counter\ntest_total 2\n");
+    }
+
+    #[test]
+    fn render_gauge_once_set() {
+        let gauge = Gauge::new("test_left", "Things left");
+        let mut out = String::new();
+        gauge.render(&mut out);
+        assert_eq!(out, "");
+
+        gauge.set(-3);
+        gauge.render(&mut out);
+        assert_eq!(out, "# HELP test_left Things left\n# TYPE test_left gauge\ntest_left -3\n");
+    }
+
+    #[test]
+    fn render_summary() {
+        let summary = Summary::new("test_seconds", "Time taken");
+        summary.observe(Duration::from_millis(1500));
+        summary.observe(Duration::from_millis(500));
+
+        let mut out = String::new();
+        summary.render(&mut out);
+        assert_eq!(out, "# HELP test_seconds Time taken\n# TYPE test_seconds summary\n\
+                         test_seconds_sum 2\ntest_seconds_count 2\n");
+    }
+
+    #[test]
+    fn render_every_counter() {
+        let out = render();
+        for name in [
+            "crawler_repos_cloned_total", "crawler_repos_mined_total", "crawler_repos_failed_total",
+            "crawler_files_compiled_total", "crawler_files_failed_total",
+            "crawler_compile_attempts_total", "crawler_subprocess_timeouts_total",
+        ] {
+            assert!(out.contains(&format!("\n# TYPE {} counter\n", name)), "{}", name);
+            assert!(out.lines().any(|l| l.starts_with(&format!("{} ", name))), "{}", name);
+        }
+        assert!(out.starts_with("# HELP crawler_repos_cloned_total "));
+        assert!(out.contains("# TYPE crawler_intern_seconds summary\n"));
+    }
+}
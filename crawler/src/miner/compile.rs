@@ -1,7 +1,10 @@
 use super::dep_graph::DepGraph;
+use super::failure::{self, Diagnosis};
 use super::select::Selector;
 use super::types::{Declare, File};
 use crate::config::Config;
+use crate::metrics;
+use crate::process::{self, Usage};
 use crate::interface::{
     CompileInput, CompileResult, Interface, MatchData, PreInput
 };
@@ -10,7 +13,12 @@ use std::collections::HashSet;
 use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use log::{error, debug};
+use serde_json::{json, Value};
+
+/// Number of bytes of each run's stderr kept in the log.
+const MAX_STDERR: usize = 4096;
 
 /// This struct contains the functionality to compile a single source file.
 pub struct Compiler<'a> {
@@ -21,13 +29,18 @@ pub struct Compiler<'a> {
     // File we are compiling
     root_dir: &'a PathBuf,  // Directory of the repository
     file: File,             // File we are compiling
+    submodule: Option<&'a PathBuf>, // Submodule the file belongs to
 
     // Header selection
     selector: Selector<'a>,
     tried: HashSet<Vec<PathBuf>>,
+    cached: Option<Vec<PathBuf>>,   // Headers that worked for a previous revision
+    success: Option<Vec<PathBuf>>,  // Headers that worked
+    usage: Usage,                   // Resources used by every attempt
 
-    // Log data
-    log_data: String,
+    // Log records
+    log: Vec<Value>,
+    failures: Vec<Diagnosis>,       // Why each failed attempt failed
 }
 
 impl<'a> Compiler<'a> {
@@ -39,21 +52,32 @@ impl<'a> Compiler<'a> {
         interface: Arc<dyn Interface + Send>
     ) -> Self {
         let root_dir = dg.root();
+        let submodule = dg.submodule(&file);
 
         // Create the header selector
         let selector = Selector::new(file.clone(), dg, config);
         let tried = HashSet::new();
 
-        // Start out with an empty log
-        let log_data = "".to_string();
-
         return Self {
-            config, interface, root_dir, file, selector, tried, log_data
+            config, interface, root_dir, file, submodule, selector, tried,
+            cached: None, success: None, usage: Usage::default(), log: vec![],
+            failures: vec![]
         };
     }
 
+    /// Try HEADERS before searching for other header combinations.
+    pub fn try_first(&mut self, headers: Vec<PathBuf>) {
+        self.cached = Some(headers);
+    }
+
     /// Try possible header combinations.
     pub fn run(&mut self) -> Result<MatchData, String> {
+        // Only count the processes run for this file, & only let them read
+        // this repository
+        process::take_usage();
+        process::take_runs();
+        process::set_root(Some(self.root_dir.clone()));
+
         // Preprocess the source file
         let input = PreInput {
             config: self.config,
@@ -65,19 +89,30 @@ impl<'a> Compiler<'a> {
             Ok(s) => s,
             Err(_) => {
                 error!("Failed to preprocess {:?}", self.file.path());
+                self.log.push(json!({
+                    "type": "preprocess",
+                    "file": self.file.path().to_string_lossy(),
+                    "success": false,
+                }));
                 return Err("Failed to preprocess".to_string());
             },
         };
 
         // Compile the file
         loop {
-            // Get the next possible header combination
-            let Some(headers) = self.selector.step() else {
-                return Err("Ran out of header possibilities".to_string());
+            // Get the next possible header combination, starting with any
+            // cached headers
+            let headers = match self.cached.take() {
+                Some(headers) => headers,
+                None => {
+                    let Some(headers) = self.selector.step() else {
+                        return Err("Ran out of header possibilities".to_string());
+                    };
+                    self.qualify_headers(headers)
+                },
             };
 
             // Don't try any header combination more than once
-            let headers = self.qualify_headers(headers);
             debug!("Headers: {:?}", headers);
             if self.tried.contains(&headers) {
                 debug!("Already seen headers: {:?}", headers);
@@ -86,12 +121,16 @@ impl<'a> Compiler<'a> {
             self.tried.insert(headers.clone());
 
             // Try to compile
-            let result = self.try_compile(&source, headers);
-            self.log(&result.to_log);
+            let start = Instant::now();
+            let result = self.try_compile(&source, headers.clone());
+            metrics::COMPILE_ATTEMPTS.inc();
+            self.usage.add(process::take_usage());
+            self.log_attempt(&headers, &result, start.elapsed());
 
             // Exit if we have succeeded, keep trying otherwise
             match result.data {
                 Ok(s) => {
+                    self.success = Some(headers);
                     return Ok(s);
                 },
                 Err(_) => {
@@ -112,6 +151,7 @@ impl<'a> Compiler<'a> {
             file: &self.file_full(),
             content: source,
             headers: &headers,
+            submodule: self.submodule,
         };
 
         match panic::catch_unwind(AssertUnwindSafe(|| { self.interface.compile(input) })) {
@@ -173,12 +213,78 @@ impl<'a> Compiler<'a> {
         return self.root_dir.join(self.file.path());
     }
 
-    /// Add the input string to the log.
-    fn log(&mut self, input: &str) {
-        self.log_data.push_str(input);
+    /// Add a record of trying to compile with HEADERS to the log, along with
+    /// the processes run by the attempt & why it failed.
+    fn log_attempt(&mut self, headers: &[PathBuf], result: &CompileResult, duration: Duration) {
+        let runs = process::take_runs();
+        let diagnosis = match result.data {
+            Ok(_) => None,
+            Err(_) => Some(failure::classify(&runs)),
+        };
+
+        let runs: Vec<Value> = runs.iter()
+            .map(|r| json!({
+                "command": r.command,
+                "status": r.status,
+                "signal": r.signal,
+                "timed_out": r.timed_out,
+                "duration_ms": r.duration.as_millis() as u64,
+                "stderr": truncate(&r.stderr, MAX_STDERR),
+            }))
+            .collect();
+
+        let mut record = json!({
+            "type": "attempt",
+            "file": self.file.path().to_string_lossy(),
+            "attempt": self.tried.len(),
+            "headers": headers.iter().map(|h| h.to_string_lossy()).collect::<Vec<_>>(),
+            "success": result.data.is_ok(),
+            "duration_ms": duration.as_millis() as u64,
+            "runs": runs,
+        });
+        if let Some(d) = &diagnosis {
+            record["failure"] = json!(d.failure.name());
+            record["subjects"] = json!(d.subjects);
+        }
+        if !result.to_log.is_empty() {
+            record["notes"] = json!(result.to_log);
+        }
+
+        self.log.push(record);
+        self.failures.extend(diagnosis);
+    }
+
+    /// Return the log records of compiling this file.
+    pub fn get_log(&self) -> &[Value] {
+        return &self.log;
+    }
+
+    /// Return why each failed attempt failed.
+    pub fn failures(&self) -> &[Diagnosis] {
+        return &self.failures;
+    }
+
+    /// Return the number of header combinations tried.
+    pub fn attempts(&self) -> usize {
+        return self.tried.len();
+    }
+
+    /// Return the headers that the file compiled with, if any.
+    pub fn headers(&self) -> Option<&Vec<PathBuf>> {
+        return self.success.as_ref();
     }
 
-    pub fn get_log(&self) -> &str {
-        return &self.log_data;
+    /// Return the resources used by the processes run for this file.
+    pub fn usage(&self) -> Usage {
+        return self.usage;
+    }
+}
+
+/// Return the first LEN bytes of S, without splitting a character.
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = len.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
     }
+    return &s[..end];
 }
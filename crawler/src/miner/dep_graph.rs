@@ -19,6 +19,9 @@ type AbbrevTable = HashMap<PathBuf, Vec<File>>;
 pub type Deps = HashMap<Declare, Vec<File>>;
 type Edges = HashMap<File, Deps>;
 
+/// Include declarations of each file, which can be reused between revisions.
+pub type DeclTable = HashMap<File, Vec<Declare>>;
+
 /// Dependency graph between all source and header files in a repository.
 ///
 /// The nodes in the graph are source and header files, and two files A & B have
@@ -34,11 +37,20 @@ pub struct DepGraph<'a> {
     // Graph structure
     nodes: HashSet<File>,   // Nodes are files
     edges: Edges,           // Edges are dependencies between files
+
+    // Parsed include declarations of each file
+    decls: DeclTable,
+
+    // Submodule that each file belongs to, if any
+    submodule_of: HashMap<File, PathBuf>,
 }
 
 impl<'a> DepGraph<'a> {
-    /// Create a new dependency graph rooted at ROOT_DIR.
-    pub fn new(root_dir: &'a PathBuf) -> Option<Self> {
+    /// Create a new dependency graph rooted at ROOT_DIR, reusing the
+    /// declarations in CACHE for any file not in CHANGED. If CHANGED is None,
+    /// every file is parsed. Files under one of SUBMODULES are recorded as
+    /// belonging to it.
+    pub fn new(root_dir: &'a PathBuf, cache: &DeclTable, changed: Option<&HashSet<PathBuf>>, submodules: &[PathBuf]) -> Option<Self> {
         info!("Building DP graph");
 
         // Find the source files in the repository
@@ -61,6 +73,15 @@ impl<'a> DepGraph<'a> {
             nodes.insert(file);
         }
 
+        // Find the submodule of each file
+        let mut submodule_of = HashMap::new();
+        for file in &nodes {
+            let sub = submodules.iter().find(|s| file.path().starts_with(s));
+            if let Some(sub) = sub {
+                submodule_of.insert(file.clone(), sub.clone());
+            }
+        }
+
         // For each file, add the the possible declarations to the table
         let mut abbrev: AbbrevTable = Default::default();
         for file in &nodes {
@@ -93,10 +114,30 @@ impl<'a> DepGraph<'a> {
             }
         }
 
+        // Parse the declarations of each file, unless it hasn't changed
+        let mut decls: DeclTable = HashMap::new();
+        for file in &nodes {
+            // A changed submodule only shows up as its own path
+            let cached = match changed {
+                Some(changed) if !changed.contains(file.path()) => {
+                    match submodule_of.get(file) {
+                        Some(sub) if changed.contains(sub) => None,
+                        _ => cache.get(file),
+                    }
+                },
+                _ => None,
+            };
+            let file_decls = match cached {
+                Some(d) => d.clone(),
+                None => Self::parse_declare(root_dir, file),
+            };
+            decls.insert(file.clone(), file_decls);
+        }
+
         // For each file, add edges where there are dependencies
         let mut edges: Edges = HashMap::new();
-        for file in &nodes {
-            for decl in Self::parse_declare(root_dir, file) {
+        for (file, file_decls) in &decls {
+            for decl in file_decls.iter().cloned() {
                 // Add each possible file as an edge
                 if let Some(possibilities) = abbrev.get(decl.path()) {
                     // Initialize the decl->posible table
@@ -116,6 +157,8 @@ impl<'a> DepGraph<'a> {
             root_dir,
             nodes,
             edges,
+            decls,
+            submodule_of,
         });
     }
 
@@ -163,4 +206,15 @@ impl<'a> DepGraph<'a> {
     pub fn deps(&self, file: &File) -> Option<&Deps> {
         return self.edges.get(file);
     }
+
+    /// Return the submodule FILE belongs to, or None if it belongs to the
+    /// repository itself.
+    pub fn submodule(&self, file: &File) -> Option<&PathBuf> {
+        return self.submodule_of.get(file);
+    }
+
+    /// Return the parsed declarations, to be reused by a later graph.
+    pub fn decls(&self) -> &DeclTable {
+        return &self.decls;
+    }
 }
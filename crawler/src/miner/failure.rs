@@ -0,0 +1,356 @@
+use crate::process::Run;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+
+lazy_static! {
+    // clang: `fatal error: 'foo.h' file not found`
+    // gcc:   `fatal error: foo.h: No such file or directory`
+    static ref MISSING_HEADER: Regex = Regex::new(
+        r"error: (?:'([^']+)' file not found|([^:\s]+): No such file or directory)"
+    ).unwrap();
+    static ref INCLUDE: Regex = Regex::new(r#"#\s*(?:include|import)\s*([<"])"#).unwrap();
+    static ref UNDECLARED: Regex = Regex::new(
+        r"error: (?:use of undeclared identifier|call to undeclared (?:library )?function|implicit declaration of function) '([^']+)'"
+    ).unwrap();
+    static ref UNKNOWN_TYPE: Regex = Regex::new(r"error: unknown type name '([^']+)'").unwrap();
+    static ref ERROR: Regex = Regex::new(r"(?:^|\s)(?:fatal )?error: ").unwrap();
+}
+
+/// Printed by clang & LLVM tools when they crash.
+static CRASH_MARKERS: [&str; 3] = [
+    "PLEASE submit a bug report",
+    "Stack dump:",
+    "clang frontend command failed",
+];
+
+/// Why an attempt to compile a file failed.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Failure {
+    MissingUserHeader,      // #include "..." that wasn't found
+    MissingSystemHeader,    // #include <...> that wasn't found
+    UndeclaredIdentifier,
+    UnknownType,
+    Syntax,                 // Any other compiler error, often another dialect
+    Timeout,
+    Crash,                  // A tool was killed by a signal or reported a crash
+    Other,                  // Failed without a compiler error
+}
+
+impl Failure {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Failure::MissingUserHeader    => "missing_user_header",
+            Failure::MissingSystemHeader  => "missing_system_header",
+            Failure::UndeclaredIdentifier => "undeclared_identifier",
+            Failure::UnknownType          => "unknown_type",
+            Failure::Syntax               => "syntax",
+            Failure::Timeout              => "timeout",
+            Failure::Crash                => "crash",
+            Failure::Other                => "other",
+        }
+    }
+}
+
+/// The cause of a failed attempt, along with the headers or identifiers it
+/// was missing.
+#[derive(Clone, Debug)]
+pub struct Diagnosis {
+    pub failure: Failure,
+    pub subjects: Vec<String>,
+}
+
+/// Classify a failed attempt from the RUNS of its tools.
+pub fn classify(runs: &[Run]) -> Diagnosis {
+    let none = |failure| Diagnosis { failure, subjects: vec![] };
+
+    if runs.iter().any(|r| r.timed_out) {
+        return none(Failure::Timeout);
+    }
+    let crashed = |r: &Run| {
+        r.signal.is_some() || CRASH_MARKERS.iter().any(|m| r.stderr.contains(m))
+    };
+    if runs.iter().any(crashed) {
+        return none(Failure::Crash);
+    }
+
+    // The first tool that failed decides the category
+    let Some(run) = runs.iter().find(|r| r.status != Some(0)) else {
+        return none(Failure::Other);
+    };
+    let lines: Vec<&str> = run.stderr.lines().collect();
+    let Some(first) = lines.iter().position(|l| ERROR.is_match(l)) else {
+        return none(Failure::Other);
+    };
+
+    let failure = if MISSING_HEADER.is_match(lines[first]) {
+        header_kind(&lines, first)
+    } else if UNDECLARED.is_match(lines[first]) {
+        Failure::UndeclaredIdentifier
+    } else if UNKNOWN_TYPE.is_match(lines[first]) {
+        Failure::UnknownType
+    } else {
+        Failure::Syntax
+    };
+
+    // Report every missing header or identifier of the same kind
+    let mut subjects = BTreeSet::new();
+    for (i, line) in lines.iter().enumerate() {
+        let subject = match failure {
+            Failure::MissingUserHeader | Failure::MissingSystemHeader => {
+                MISSING_HEADER.captures(line)
+                    .filter(|_| header_kind(&lines, i) == failure)
+                    .and_then(|c| c.get(1).or(c.get(2)))
+            },
+            Failure::UndeclaredIdentifier => UNDECLARED.captures(line).and_then(|c| c.get(1)),
+            Failure::UnknownType => UNKNOWN_TYPE.captures(line).and_then(|c| c.get(1)),
+            _ => None,
+        };
+        if let Some(s) = subject {
+            subjects.insert(s.as_str().to_string());
+        }
+    }
+
+    return Diagnosis { failure, subjects: subjects.into_iter().collect() };
+}
+
+/// Return whether the missing header reported on line I of LINES was
+/// included with quotes or angle brackets, from the include that is quoted
+/// on the next line.
+fn header_kind(lines: &[&str], i: usize) -> Failure {
+    let bracket = lines.iter()
+        .skip(i + 1)
+        .take(2)
+        .find_map(|l| INCLUDE.captures(l))
+        .map(|c| c[1].to_string());
+
+    match bracket.as_deref() {
+        Some("<") => Failure::MissingSystemHeader,
+        _ => Failure::MissingUserHeader,
+    }
+}
+
+/// Counts of failed attempts by category, & of the files missing each header
+/// or identifier.
+#[derive(Clone, Debug, Default)]
+pub struct FailureCounts {
+    pub attempts: HashMap<Failure, i64>,
+    pub subjects: HashMap<(Failure, String), i64>,
+}
+
+impl FailureCounts {
+    /// Add the DIAGNOSES of a file's failed attempts. Missing headers &
+    /// identifiers are only counted once per file, & only if the file never
+    /// compiled.
+    pub fn add_file(&mut self, diagnoses: &[Diagnosis], compiled: bool) {
+        let mut subjects = BTreeSet::new();
+        for d in diagnoses {
+            *self.attempts.entry(d.failure).or_default() += 1;
+            for s in &d.subjects {
+                subjects.insert((d.failure, s.clone()));
+            }
+        }
+
+        if !compiled {
+            for key in subjects {
+                *self.subjects.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    /// Add the counts of OTHER.
+    pub fn merge(&mut self, other: &FailureCounts) {
+        for (failure, n) in &other.attempts {
+            *self.attempts.entry(*failure).or_default() += n;
+        }
+        for (key, n) in &other.subjects {
+            *self.subjects.entry(key.clone()).or_default() += n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(status: Option<i32>, stderr: &str) -> Run {
+        return Run {
+            command: "clang".to_string(),
+            status,
+            signal: None,
+            timed_out: false,
+            duration: Duration::ZERO,
+            stderr: stderr.to_string(),
+        };
+    }
+
+    fn diagnose(stderr: &str) -> (Failure, Vec<String>) {
+        let d = classify(&[run(Some(1), stderr)]);
+        return (d.failure, d.subjects);
+    }
+
+    fn diagnosis(failure: Failure, subjects: &[&str]) -> Diagnosis {
+        return Diagnosis { failure, subjects: subjects.iter().map(|s| s.to_string()).collect() };
+    }
+
+    #[test]
+    fn missing_user_header() {
+        let stderr = "\
+src/main.c:3:10: fatal error: 'config.h' file not found
+    3 | #include \"config.h\"
+      |          ^~~~~~~~~~
+1 error generated.
+";
+        assert_eq!(diagnose(stderr), (Failure::MissingUserHeader, vec!["config.h".to_string()]));
+    }
+
+    #[test]
+    fn missing_system_header() {
+        let stderr = "\
+src/net.c:1:10: fatal error: 'openssl/ssl.h' file not found
+    1 | #include <openssl/ssl.h>
+      |          ^~~~~~~~~~~~~~~
+1 error generated.
+";
+        assert_eq!(diagnose(stderr), (Failure::MissingSystemHeader, vec!["openssl/ssl.h".to_string()]));
+    }
+
+    #[test]
+    fn missing_headers_of_first_kind() {
+        let stderr = "\
+src/a.c:1:10: fatal error: zlib.h: No such file or directory
+    1 | #include <zlib.h>
+      |          ^~~~~~~~
+src/b.c:2:10: fatal error: util.h: No such file or directory
+    2 | #include \"util.h\"
+      |          ^~~~~~~~
+src/c.c:1:10: fatal error: bzlib.h: No such file or directory
+    1 | #include <bzlib.h>
+      |          ^~~~~~~~~
+";
+        let subjects = vec!["bzlib.h".to_string(), "zlib.h".to_string()];
+        assert_eq!(diagnose(stderr), (Failure::MissingSystemHeader, subjects));
+    }
+
+    #[test]
+    fn header_kind_from_include() {
+        let lines = ["a.c:1:10: fatal error: 'b.h' file not found", "    1 | #  include <b.h>"];
+        assert_eq!(header_kind(&lines, 0), Failure::MissingSystemHeader);
+        let lines = ["a.m:1:9: fatal error: 'b.h' file not found", "    1 | #import \"b.h\""];
+        assert_eq!(header_kind(&lines, 0), Failure::MissingUserHeader);
+
+        // Without the include, the header is assumed to be the project's
+        let lines = ["a.c:1:10: fatal error: 'b.h' file not found"];
+        assert_eq!(header_kind(&lines, 0), Failure::MissingUserHeader);
+    }
+
+    #[test]
+    fn undeclared_identifier() {
+        let stderr = "\
+src/main.c:5:5: error: call to undeclared function 'foo'; ISO C99 and later do not support implicit function declarations [-Wimplicit-function-declaration]
+    5 |     foo(BAR);
+      |     ^
+src/main.c:5:9: error: use of undeclared identifier 'BAR'
+    5 |     foo(BAR);
+      |         ^
+src/main.c:6:5: error: use of undeclared identifier 'BAR'
+2 errors generated.
+";
+        let subjects = vec!["BAR".to_string(), "foo".to_string()];
+        assert_eq!(diagnose(stderr), (Failure::UndeclaredIdentifier, subjects));
+    }
+
+    #[test]
+    fn unknown_type() {
+        let stderr = "\
+src/list.h:4:5: error: unknown type name 'u8'
+    4 |     u8 len;
+      |     ^
+src/list.h:5:5: error: unknown type name 'list_t'
+1 error generated.
+";
+        let subjects = vec!["list_t".to_string(), "u8".to_string()];
+        assert_eq!(diagnose(stderr), (Failure::UnknownType, subjects));
+    }
+
+    #[test]
+    fn syntax() {
+        let stderr = "\
+src/main.c:7:14: warning: unused variable 'x' [-Wunused-variable]
+src/main.c:8:12: error: expected ';' after expression
+    8 |     return 0
+      |             ^
+      |             ;
+src/main.c:9:5: error: use of undeclared identifier 'y'
+1 error generated.
+";
+        assert_eq!(diagnose(stderr), (Failure::Syntax, vec![]));
+    }
+
+    #[test]
+    fn first_failed_tool_decides() {
+        let runs = [
+            run(Some(0), "src/main.c:1:1: warning: error: in a warning\n"),
+            run(Some(1), "src/main.c:2:1: error: unknown type name 'size_t'\n"),
+            run(Some(1), "src/main.c:3:1: error: expected expression\n"),
+        ];
+        assert_eq!(classify(&runs).failure, Failure::UnknownType);
+    }
+
+    #[test]
+    fn timeout_and_crash() {
+        let mut timed_out = run(None, "");
+        timed_out.timed_out = true;
+        let mut killed = run(None, "");
+        killed.signal = Some(libc::SIGSEGV);
+        let reported = run(Some(1), "\
+PLEASE submit a bug report to https://github.com/llvm/llvm-project/issues/ and include the crash backtrace, preprocessed source, and associated run script.
+Stack dump:
+0.	Program arguments: clang -c src/main.c
+clang: error: clang frontend command failed with exit code 139 (use -v to see invocation)
+");
+
+        assert_eq!(classify(&[killed.clone(), timed_out]).failure, Failure::Timeout);
+        assert_eq!(classify(&[killed]).failure, Failure::Crash);
+        assert_eq!(classify(&[reported]).failure, Failure::Crash);
+    }
+
+    #[test]
+    fn other() {
+        assert_eq!(diagnose("ld: cannot open output file a.out\n").0, Failure::Other);
+        assert_eq!(classify(&[run(Some(0), "")]).failure, Failure::Other);
+    }
+
+    #[test]
+    fn subjects_counted_once_per_failing_file() {
+        let missing = diagnosis(Failure::MissingSystemHeader, &["zlib.h"]);
+        let undeclared = diagnosis(Failure::UndeclaredIdentifier, &["foo"]);
+
+        let mut counts = FailureCounts::default();
+        counts.add_file(&[missing.clone(), missing.clone(), undeclared.clone()], false);
+        counts.add_file(&[missing.clone()], true);
+
+        assert_eq!(counts.attempts[&Failure::MissingSystemHeader], 3);
+        assert_eq!(counts.attempts[&Failure::UndeclaredIdentifier], 1);
+        assert_eq!(counts.subjects[&(Failure::MissingSystemHeader, "zlib.h".to_string())], 1);
+        assert_eq!(counts.subjects[&(Failure::UndeclaredIdentifier, "foo".to_string())], 1);
+    }
+
+    #[test]
+    fn merge_counts() {
+        let missing = diagnosis(Failure::MissingUserHeader, &["config.h"]);
+        let mut a = FailureCounts::default();
+        a.add_file(&[missing.clone()], false);
+        let mut b = FailureCounts::default();
+        b.add_file(&[missing.clone()], false);
+        b.add_file(&[diagnosis(Failure::Syntax, &[])], false);
+
+        a.merge(&b);
+        assert_eq!(a.attempts[&Failure::MissingUserHeader], 2);
+        assert_eq!(a.attempts[&Failure::Syntax], 1);
+        assert_eq!(a.subjects[&(Failure::MissingUserHeader, "config.h".to_string())], 2);
+        assert_eq!(a.subjects.len(), 1);
+    }
+}
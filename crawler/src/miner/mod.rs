@@ -1,62 +1,147 @@
 mod compile;
 mod dep_graph;
 mod extract;
+mod failure;
+mod repo_log;
 mod select;
 mod types;
 
 use crate::interface::AnyInterface;
 use compile::Compiler;
-use dep_graph::DepGraph;
+use dep_graph::{DeclTable, DepGraph};
+use repo_log::RepoLog;
+use types::File;
+pub use failure::{Failure, FailureCounts};
 use crate::config::Config;
 use crate::interface::MatchData;
+use crate::metrics;
+use crate::process::Usage;
 
-use std::fs;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::process::Command;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, mpsc};
+use std::time::Instant;
 use rayon::prelude::*;
 use log::{debug, info, error, warn};
+use serde_json::json;
 
 pub struct MineResult {
     pub data: Vec<MatchData>,
     pub n_files: i64,
     pub n_success: i64,
     pub n_error: i64,
+    pub n_skipped: i64,                 // Files left when the time budget ran out
+    pub usage: Vec<(PathBuf, Usage)>,   // Resources used to compile each file
+    pub failures: FailureCounts,        // Why attempts failed
+}
+
+/// State kept between mining different revisions of the same repository.
+#[derive(Default)]
+pub struct MineCache {
+    decls: DeclTable,                       // Include declarations of each file
+    headers: HashMap<File, Vec<PathBuf>>,   // Headers each file compiled with
+    changed: Option<HashSet<PathBuf>>,      // Files changed since the last revision
+    submodules: Vec<PathBuf>,               // Paths of initialized submodules
+    deadline: Option<Instant>,              // No new files are started after this
+    progress: Arc<FileProgress>,            // Files compiled so far
+}
+
+/// Number of files mined, which can be read by other threads while mining.
+#[derive(Debug, Default)]
+pub struct FileProgress {
+    pub total: AtomicUsize,     // Files to try, over every revision mined
+    pub done: AtomicUsize,      // Files that have been tried
+    pub success: AtomicUsize,   // Files that compiled
+}
+
+impl MineCache {
+    pub fn new() -> Self {
+        return Default::default();
+    }
+
+    /// Set the files that have changed since the cache was last used. Cached
+    /// declarations are only used for files that haven't changed.
+    pub fn set_changed(&mut self, changed: Option<HashSet<PathBuf>>) {
+        self.changed = changed;
+    }
+
+    /// Set the paths of the submodules in the repository, relative to its
+    /// root.
+    pub fn set_submodules(&mut self, submodules: Vec<PathBuf>) {
+        self.submodules = submodules;
+    }
+
+    /// Stop compiling new files after DEADLINE. Files that haven't been
+    /// started by then are counted as skipped.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Count the files compiled in PROGRESS, so it can be watched from
+    /// another thread.
+    pub fn set_progress(&mut self, progress: Arc<FileProgress>) {
+        self.progress = progress;
+    }
 }
 
 /// Build a dependency graph of the source an header files in DIRECTORY.
 ///
 /// Currently, only `*.c` and `*.h` files are supported.
 pub fn mine(directory: &PathBuf, log_file: &PathBuf, config: Config, interface: AnyInterface) -> Result<MineResult, ()> {
+    return mine_cached(directory, log_file, config, interface, &mut MineCache::new());
+}
+
+/// Mine DIRECTORY, reusing & updating the results in CACHE.
+pub fn mine_cached(directory: &PathBuf, log_file: &PathBuf, config: Config, interface: AnyInterface, cache: &mut MineCache) -> Result<MineResult, ()> {
     // Build the dependency graph
-    let dg = DepGraph::new(directory);
+    let dg = DepGraph::new(directory, &cache.decls, cache.changed.as_ref(), &cache.submodules);
     let Some(dg) = dg else {
         warn!("Failed to build DP graph");
         return Err(());
     };
+    cache.decls = dg.decls().clone();
+    let cached_headers = &cache.headers;
+    let deadline = cache.deadline;
+    let progress = &cache.progress;
+    let skipped = AtomicI64::new(0);
+    let found_headers = Mutex::new(HashMap::new());
+    let usage = Mutex::new(vec![]);
+    let failures = Mutex::new(FailureCounts::default());
 
     // Open the log file
-    let log = match fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(log_file) {
-        Ok(f) => f,
+    let log = match RepoLog::open(log_file) {
+        Ok(l) => l,
         Err(e) => {
             error!("Failed to open log file: {:?}", e);
             return Err(());
         },
     };
-    let log = Arc::new(Mutex::new(log));
+
+    // Submodules are only used for their headers, unless configured otherwise
+    let mine_submodules = config.submodules.as_ref().is_some_and(|s| s.mine_sources);
+    let sources: Vec<File> = dg.source_files()
+        .into_iter()
+        .filter(|f| mine_submodules || dg.submodule(f).is_none())
+        .collect();
 
     // Count the number of files that fail
-    let total: i64 = dg.source_files().len() as i64;
+    let total: i64 = sources.len() as i64;
+    progress.total.fetch_add(sources.len(), Ordering::Relaxed);
     let (tx, rx) = mpsc::channel::<i64>();
 
     // Compile each file
     info!("Starting compilation");
-    let match_data: Vec<MatchData> = dg.source_files().par_iter()
+    let match_data: Vec<MatchData> = sources.par_iter()
         .filter_map(|file| {
+            // Leave the remaining files once out of time. They no longer
+            // count towards the total, so the progress can still reach it.
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                skipped.fetch_add(1, Ordering::Relaxed);
+                progress.total.fetch_sub(1, Ordering::Relaxed);
+                return None;
+            }
+
             let tx = tx.clone();
 
             let result = std::panic::catch_unwind(|| {
@@ -67,29 +152,54 @@ pub fn mine(directory: &PathBuf, log_file: &PathBuf, config: Config, interface:
                     &config,
                     interface.clone()
                 );
+                if let Some(headers) = cached_headers.get(file) {
+                    compiler.try_first(headers.clone());
+                }
 
                 let comp_result = match compiler.run() {
                     Ok(data) => {
                         tx.send(1).unwrap();
+                        progress.success.fetch_add(1, Ordering::Relaxed);
+                        metrics::FILES_COMPILED.inc();
                         Some(data)
                     },
                     Err(e) => {
                         debug!("Failed completely for {:?}: {}", file.path(), e);
                         tx.send(0).unwrap();
+                        metrics::FILES_FAILED.inc();
                         None
                     },
                 };
 
-                // Send the compiler output
-                {
-                    let mut outfile = log.lock().unwrap();
-                    outfile.write_all(compiler.get_log().as_bytes()).unwrap();
+                // Send the compiler output, followed by a summary of the file
+                let file_usage = compiler.usage();
+                let mut file_failures = FailureCounts::default();
+                file_failures.add_file(compiler.failures(), comp_result.is_some());
+                log.write(compiler.get_log());
+                log.write(&[json!({
+                    "type": "file",
+                    "file": file.path().to_string_lossy(),
+                    "success": comp_result.is_some(),
+                    "attempts": compiler.attempts(),
+                    "max_rss_kb": file_usage.max_rss,
+                    "cpu_time_ms": file_usage.cpu_time.as_millis() as u64,
+                    "failures": file_failures.attempts.iter()
+                        .map(|(f, n)| (f.name().to_string(), json!(n)))
+                        .collect::<serde_json::Map<_, _>>(),
+                })]);
+                usage.lock().unwrap().push((file.path().clone(), file_usage));
+                failures.lock().unwrap().merge(&file_failures);
+
+                // Remember the headers for the next revision
+                if let Some(headers) = compiler.headers() {
+                    found_headers.lock().unwrap().insert(file.clone(), headers.clone());
                 }
 
                 return comp_result;
             });
 
             drop(tx);
+            progress.done.fetch_add(1, Ordering::Relaxed);
 
             // If there was a panic, print so
             match result {
@@ -104,26 +214,23 @@ pub fn mine(directory: &PathBuf, log_file: &PathBuf, config: Config, interface:
     // Gather the counts
     drop(tx);
     let success: i64 = rx.iter().sum();
-    info!("Results: total: {}, successful: {}", total, success);
-
-    // Compress the log file
-    let compress = Command::new("tar")
-        .arg("-czf")
-        .arg(&format!("{}.tar.gz", log_file.to_str().unwrap()))
-        .arg(log_file)
-        .output();
-
-    match compress {
-        Ok(_) => { let _ = fs::remove_file(log_file); }
-        Err(e) => { error!("Failed to compress logfile: {:?}", e); }
+    let skipped = skipped.into_inner();
+    info!("Results: total: {}, successful: {}, skipped: {}", total, success, skipped);
+    if skipped > 0 {
+        warn!("Time budget ran out, skipped {} files", skipped);
     }
-    drop(log);
+    cache.headers = found_headers.into_inner().unwrap();
+
+    log.finish();
 
     return Ok(MineResult {
         data: match_data,
         n_files: total,
         n_success: success,
-        n_error: total - success,
+        n_error: total - success - skipped,
+        n_skipped: skipped,
+        usage: usage.into_inner().unwrap(),
+        failures: failures.into_inner().unwrap(),
     });
 }
 
@@ -135,6 +242,6 @@ pub fn mine_one(directory: PathBuf, config: Config, interface: AnyInterface) {
         .build_global()
         .expect("Failed to create miner thread pool");
 
-    let log_file = config.runner.log_dir.join("repo.log");
+    let log_file = config.runner.log_dir.join("repo.jsonl.gz");
     let _ = mine(&directory, &log_file, config, interface);
 }
@@ -0,0 +1,52 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::error;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Log of mining a repository, written as gzip compressed JSON lines, one
+/// record per line.
+///
+/// Records are compressed as they are written. Opening an existing log adds
+/// another gzip member to the end, which is read as part of the same stream.
+pub struct RepoLog {
+    out: Mutex<GzEncoder<File>>,
+}
+
+impl RepoLog {
+    /// Open the log at PATH, adding to it if it exists.
+    pub fn open(path: &PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        return Ok(Self { out: Mutex::new(GzEncoder::new(file, Compression::default())) });
+    }
+
+    /// Add RECORDS to the log.
+    pub fn write(&self, records: &[Value]) {
+        let mut out = self.out.lock().unwrap();
+        for record in records {
+            let result = serde_json::to_writer(&mut *out, record)
+                .map_err(io::Error::from)
+                .and_then(|_| out.write_all(b"\n"));
+
+            if let Err(e) = result {
+                error!("Failed to write to log: {:?}", e);
+                return;
+            }
+        }
+    }
+
+    /// Finish compressing the log.
+    pub fn finish(self) {
+        let out = self.out.into_inner().unwrap();
+        if let Err(e) = out.finish() {
+            error!("Failed to finish log: {:?}", e);
+        }
+    }
+}
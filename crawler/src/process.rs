@@ -0,0 +1,402 @@
+use crate::config::Miner;
+use crate::metrics;
+use crate::sandbox::Sandbox;
+
+use std::cell::{Cell, RefCell};
+use std::io::{self, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+
+// Shared path for running external tools, such as clang & opt, from the
+// interfaces.
+//
+// The number of tools running at once is limited by a pool of job slots, &
+// each tool is started with the configured resource limits, optionally in a
+// sandbox. Each tool runs in its own process group, which is killed once the
+// tool exits, so no descendants are left behind.
+
+static SLOTS: OnceLock<Slots> = OnceLock::new();
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+static SANDBOX: AtomicBool = AtomicBool::new(false);
+
+/// Process groups of the tools that are running, so they can be killed if the
+/// crawler has to exit while they run.
+static GROUPS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// Time to wait for the output pipes to close once a tool has exited. A
+/// descendant that left the tool's process group can keep them open.
+static PIPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+thread_local! {
+    // Usage of the processes run by this thread since it was last taken
+    static USAGE: Cell<Usage> = const { Cell::new(Usage::ZERO) };
+
+    // Processes run by this thread since they were last taken
+    static RUNS: RefCell<Vec<Run>> = const { RefCell::new(Vec::new()) };
+
+    // Repository that the sandbox allows access to
+    static ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Resources used by one or more processes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub max_rss: u64,           // In KB
+    pub cpu_time: Duration,     // User & system time
+}
+
+impl Usage {
+    const ZERO: Usage = Usage { max_rss: 0, cpu_time: Duration::ZERO };
+
+    /// Combine with the usage of OTHER.
+    pub fn add(&mut self, other: Usage) {
+        self.max_rss = self.max_rss.max(other.max_rss);
+        self.cpu_time += other.cpu_time;
+    }
+}
+
+/// Record of a process that was run, for the repository's log.
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub command: String,
+    pub status: Option<i32>,    // Exit code, None if killed by a signal
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub duration: Duration,
+    pub stderr: String,
+}
+
+/// Resource limits applied to each process.
+#[derive(Clone, Copy, Debug, Default)]
+struct Limits {
+    address_space: Option<u64>, // In bytes
+    cpu_time: Option<u64>,      // In seconds
+}
+
+/// Job slots, which must be held to run a process.
+struct Slots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A job slot, which is released when dropped.
+struct Slot<'a>(&'a Slots);
+
+/// Output pipe of a process, read on its own thread so a full pipe can't
+/// block the process.
+struct Pipe {
+    buf: Arc<Mutex<Vec<u8>>>,
+    closed: Receiver<()>,   // Disconnected once the pipe is closed
+}
+
+impl Slots {
+    fn acquire(&self) -> Slot<'_> {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            free = self.released.wait(free).unwrap();
+        }
+        *free -= 1;
+        return Slot(self);
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl Pipe {
+    /// Start reading PIPE until it is closed.
+    fn read(mut pipe: impl Read + Send + 'static) -> Self {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let (tx, closed) = mpsc::channel();
+
+        let acc = buf.clone();
+        thread::spawn(move || {
+            let _tx = tx;
+            let mut chunk = [0; 8192];
+            while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+                acc.lock().unwrap().extend_from_slice(&chunk[..n]);
+            }
+        });
+
+        return Self { buf, closed };
+    }
+
+    /// Return what was read once the pipe is closed, or what was read so far
+    /// once DEADLINE passes.
+    fn finish(self, deadline: Instant) -> Vec<u8> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        if let Err(RecvTimeoutError::Timeout) = self.closed.recv_timeout(wait) {
+            warn!("Output pipe is still open after the process exited, its output may be incomplete");
+        }
+        return std::mem::take(&mut *self.buf.lock().unwrap());
+    }
+}
+
+/// Set the job slots & resource limits from CONFIG. Must be called before any
+/// process is run, later calls are ignored.
+pub fn configure(config: &Miner) {
+    let slots = match config.max_processes {
+        Some(n) => n,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let limits = Limits {
+        address_space: config.max_memory.map(|mb| mb * 1024 * 1024),
+        cpu_time: config.max_cpu_time,
+    };
+
+    info!("Running at most {} processes, with limits {:?}", slots, limits);
+    let _ = SLOTS.set(Slots { free: Mutex::new(slots), released: Condvar::new() });
+    let _ = LIMITS.set(limits);
+
+    if config.sandbox {
+        enable_sandbox();
+    }
+}
+
+/// Use the sandbox for every process, if this kernel supports it.
+fn enable_sandbox() {
+    SANDBOX.store(true, Ordering::SeqCst);
+
+    match output(&mut Command::new("true")) {
+        Ok(out) if out.status.success() => {
+            info!("Running external tools in a sandbox");
+        },
+        Ok(out) => {
+            SANDBOX.store(false, Ordering::SeqCst);
+            error!("Sandbox test failed with {}, running external tools without a sandbox", out.status);
+        },
+        Err(e) => {
+            SANDBOX.store(false, Ordering::SeqCst);
+            error!(
+                "Sandbox is not supported ({}). Unprivileged user namespaces may be \
+                 disabled by the kernel, for example by the \
+                 `kernel.unprivileged_userns_clone` or `user.max_user_namespaces` \
+                 sysctls. Running external tools without a sandbox.",
+                e
+            );
+        },
+    }
+}
+
+/// Set the repository that processes run by this thread can read when
+/// sandboxed.
+pub(crate) fn set_root(root: Option<PathBuf>) {
+    ROOT.with(|r| *r.borrow_mut() = root);
+}
+
+/// Kill the process groups of every tool that is running, before the crawler
+/// exits without waiting for them.
+pub fn kill_all() {
+    let groups = GROUPS.lock().unwrap();
+    for group in groups.iter() {
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+    }
+    if !groups.is_empty() {
+        warn!("Killed {} running tools", groups.len());
+    }
+}
+
+/// Run CMD to completion & collect its output, like `Command::output`.
+pub fn output(cmd: &mut Command) -> io::Result<Output> {
+    return run(cmd, None);
+}
+
+/// Run CMD to completion with INPUT as its stdin, & collect its output.
+pub fn output_with_input(cmd: &mut Command, input: &[u8]) -> io::Result<Output> {
+    return run(cmd, Some(input));
+}
+
+/// Return the processes this thread has run since the last call.
+pub fn take_runs() -> Vec<Run> {
+    return RUNS.with(|r| r.take());
+}
+
+/// Return the resources used by the processes this thread has run since the
+/// last call.
+pub fn take_usage() -> Usage {
+    return USAGE.with(|u| u.replace(Usage::default()));
+}
+
+fn run(cmd: &mut Command, input: Option<&[u8]>) -> io::Result<Output> {
+    // Wait for a job slot
+    let _slot = SLOTS.get().map(|s| s.acquire());
+
+    // Prepare the sandbox before forking
+    let sandbox = match SANDBOX.load(Ordering::SeqCst) {
+        true => Some(ROOT.with(|r| Sandbox::new(r.borrow().as_ref()))?),
+        false => None,
+    };
+
+    // Set up the child before it starts the tool
+    let limits = LIMITS.get().cloned().unwrap_or_default();
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            apply_limits(&limits)?;
+            if let Some(sandbox) = &sandbox {
+                sandbox.enter()?;
+            }
+            return Ok(());
+        });
+    }
+
+    let start = Instant::now();
+    let mut child = cmd
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    GROUPS.lock().unwrap().push(child.id() as libc::pid_t);
+
+    // Read & write the pipes on separate threads, so a full pipe can't block
+    // the process. Threads that are still blocked once it exits are left
+    // behind, rather than blocking the caller.
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+    let out = Pipe::read(child.stdout.take().unwrap());
+    let err = Pipe::read(child.stderr.take().unwrap());
+
+    let (status, usage) = wait(child.id());
+    let deadline = Instant::now() + PIPE_TIMEOUT;
+    let (out, err) = (out.finish(deadline), err.finish(deadline));
+
+    let status = status?;
+    let timed_out = timed_out(cmd, &status);
+    if timed_out {
+        metrics::TIMEOUTS.inc();
+    }
+
+    let run = Run {
+        command: command_line(cmd),
+        status: status.code(),
+        signal: status.signal(),
+        timed_out,
+        duration: start.elapsed(),
+        stderr: String::from_utf8_lossy(&err).to_string(),
+    };
+    RUNS.with(|r| r.borrow_mut().push(run));
+    USAGE.with(|u| {
+        let mut total = u.get();
+        total.add(usage);
+        u.set(total);
+    });
+
+    return Ok(Output { status, stdout: out, stderr: err });
+}
+
+/// Return CMD as it would be typed in a shell.
+fn command_line(cmd: &Command) -> String {
+    let program = std::iter::once(cmd.get_program());
+    let words: Vec<String> = program.chain(cmd.get_args())
+        .map(|w| {
+            let w = w.to_string_lossy();
+            match w.contains(char::is_whitespace) || w.is_empty() {
+                true => format!("'{}'", w.replace('\'', "'\\''")),
+                false => w.to_string(),
+            }
+        })
+        .collect();
+    return words.join(" ");
+}
+
+/// Return true if the process run by CMD was killed for taking too long,
+/// either by `timeout` or the CPU time limit.
+fn timed_out(cmd: &Command, status: &ExitStatus) -> bool {
+    if cmd.get_program() == "timeout" && matches!(status.code(), Some(124) | Some(137)) {
+        return true;
+    }
+    return status.signal() == Some(libc::SIGXCPU);
+}
+
+/// Set the resource limits of the current process. Only async-signal-safe
+/// functions can be called here, as it runs between fork & exec.
+fn apply_limits(limits: &Limits) -> io::Result<()> {
+    if let Some(bytes) = limits.address_space {
+        set_limit(libc::RLIMIT_AS, bytes)?;
+    }
+    if let Some(secs) = limits.cpu_time {
+        set_limit(libc::RLIMIT_CPU, secs)?;
+    }
+    return Ok(());
+}
+
+fn set_limit(resource: libc::__rlimit_resource_t, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/// Wait for the process PID to exit, kill anything it left running in its
+/// process group, & return its status & resource usage.
+///
+/// The usage includes any descendants the process waited for, such as the
+/// tool started by `timeout`.
+fn wait(pid: u32) -> (io::Result<ExitStatus>, Usage) {
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    // Wait without reaping, so the process group can't be reused by another
+    // process before it is killed
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let flags = libc::WEXITED | libc::WNOWAIT;
+        let ret = unsafe { libc::waitid(libc::P_PID, pid, &mut info, flags) };
+        if ret != -1 {
+            break;
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            warn!("Failed to wait for process {}: {}", pid, err);
+            break;
+        }
+    }
+
+    // Kill anything the tool left running, which could keep the pipes open.
+    // The group is forgotten before the tool is reaped, after which its id
+    // could be reused.
+    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    GROUPS.lock().unwrap().retain(|g| *g != pid as libc::pid_t);
+
+    loop {
+        let ret = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut rusage) };
+        if ret != -1 {
+            break;
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            warn!("Failed to wait for process {}: {}", pid, err);
+            return (Err(err), Usage::default());
+        }
+    }
+
+    let time = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    let usage = Usage {
+        max_rss: rusage.ru_maxrss as u64,
+        cpu_time: time(rusage.ru_utime) + time(rusage.ru_stime),
+    };
+
+    return (Ok(ExitStatus::from_raw(status)), usage);
+}
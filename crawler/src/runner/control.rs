@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::process;
+
+use tokio::signal::unix::{signal, SignalKind};
+use log::{error, info, warn};
+
+/// Name of the file in the log directory that pauses the crawl while it exists.
+static PAUSE_FILE: &str = "PAUSE";
+
+/// How often to check whether a paused crawl can continue.
+static PAUSE_POLL: Duration = Duration::from_secs(5);
+
+/// Shared state that lets a running crawl be stopped or paused.
+///
+/// The first SIGINT or SIGTERM stops new repositories from being started, &
+/// the second exits immediately. Repositories being mined are finished, since
+/// the tools run in their own process groups & don't get the terminal's
+/// signals. SIGUSR1 toggles whether the crawl is paused, as does creating or
+/// deleting the pause file.
+pub struct Control {
+    stops: AtomicUsize,     // Number of stop signals received
+    paused: AtomicBool,     // Paused by SIGUSR1
+    pause_file: PathBuf,
+}
+
+impl Control {
+    /// Create the control, & start handling signals.
+    pub fn install(config: &Config) -> Arc<Self> {
+        // The configured log directory, not the sub-directory for this run
+        let log_dir = &config.runner.log_dir;
+        let pause_file = log_dir.parent().unwrap_or(log_dir).join(PAUSE_FILE);
+
+        let control = Arc::new(Self {
+            stops: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            pause_file,
+        });
+
+        let handler = control.clone();
+        let spawned = thread::Builder::new()
+            .name("signals".to_string())
+            .spawn(move || handler.handle_signals());
+        if let Err(e) = spawned {
+            error!("Failed to start signal handler: {}", e);
+        }
+
+        return control;
+    }
+
+    /// Wait for signals until the process exits.
+    fn handle_signals(&self) {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create signal runtime");
+
+        rt.block_on(async {
+            let mut int = signal(SignalKind::interrupt()).expect("Failed to handle SIGINT");
+            let mut term = signal(SignalKind::terminate()).expect("Failed to handle SIGTERM");
+            let mut usr1 = signal(SignalKind::user_defined1()).expect("Failed to handle SIGUSR1");
+
+            loop {
+                tokio::select! {
+                    _ = int.recv() => self.stop(),
+                    _ = term.recv() => self.stop(),
+                    _ = usr1.recv() => self.toggle_pause(),
+                }
+            }
+        });
+    }
+
+    /// Stop starting new repositories, or exit if already stopping.
+    fn stop(&self) {
+        let stops = self.stops.fetch_add(1, Ordering::SeqCst) + 1;
+        if stops == 1 {
+            warn!("Stopping after the current repositories, signal again to quit immediately");
+            return;
+        }
+
+        // Tools run in their own process groups, so they wouldn't get the
+        // signal & would keep running
+        error!("Quitting immediately");
+        process::kill_all();
+        log::logger().flush();
+        std::process::exit(130);
+    }
+
+    /// Pause the crawl if it is running, or resume it if it is paused.
+    fn toggle_pause(&self) {
+        let paused = !self.paused.fetch_xor(true, Ordering::SeqCst);
+        match paused {
+            true => info!("Pausing after the current repositories"),
+            false => info!("Resuming"),
+        }
+    }
+
+    /// Return true if the crawl has been asked to stop.
+    pub fn stopping(&self) -> bool {
+        return self.stops.load(Ordering::SeqCst) > 0;
+    }
+
+    /// Return true if the crawl is paused, by signal or by the pause file.
+    pub fn paused(&self) -> bool {
+        return self.paused.load(Ordering::SeqCst) || self.pause_file.exists();
+    }
+
+    /// Block while the crawl is paused, & return false if the next repository
+    /// shouldn't be started.
+    pub fn proceed(&self) -> bool {
+        let mut waited = false;
+        while !self.stopping() && self.paused() {
+            if !waited {
+                info!("Paused, remove {:?} or send SIGUSR1 to resume", self.pause_file);
+                waited = true;
+            }
+            thread::sleep(PAUSE_POLL);
+        }
+
+        return !self.stopping();
+    }
+}
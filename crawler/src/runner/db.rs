@@ -2,7 +2,7 @@ use crate::config::Config;
 
 use tokio::runtime::Runtime;
 use sqlx::pool::Pool;
-use sqlx::Any;
+use sqlx::{Any, Row};
 use sqlx::any::AnyPoolOptions;
 use log::info;
 
@@ -55,19 +55,52 @@ impl Database {
 
     /// Create the tables in the database.
     async fn create_tables(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "create table if not exists runs (
+             run_id             int auto_increment,
+             command            varchar(16),
+             crawler_version    varchar(32),
+             interface          text,
+             interface_version  varchar(32),
+             tools              text,
+             plugins            text,
+             config             text,
+             hostname           text,
+             started_at         datetime,
+             ended_at           datetime,
+             primary key (run_id)
+        )"
+        ).execute(&self.pool).await?;
+
         sqlx::query(
             "create table if not exists repos (
-             repo_id     int,
+             repo_id     bigint,
              name        text,
              clone_url   text,
              stars       int,
+             revision    text,
+             size        int,
              primary key (repo_id)
         )"
         ).execute(&self.pool).await?;
 
         sqlx::query(
             "create table if not exists mined (
-             repo_id     int,
+             repo_id     bigint,
+             commit_sha  varchar(40),
+             branch      text,
+             cloned_at   datetime,
+             run_id      int,
+             primary key (repo_id),
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists claims (
+             repo_id     bigint,
+             worker_id   varchar(255),
+             expires_at  datetime,
              primary key (repo_id),
              foreign key (repo_id) references repos
         )"
@@ -75,16 +108,181 @@ impl Database {
 
         sqlx::query(
             "create table if not exists stats (
-             repo_id     int,
+             repo_id     bigint,
              n_files     int,
              n_success   int,
              n_error     int,
              time        float,
+             peak_disk   bigint,
+             partial     boolean,
+             n_skipped   int,
+             run_id      int,
              primary key (repo_id),
              foreign key (repo_id) references repos
         )"
         ).execute(&self.pool).await?;
 
+        sqlx::query(
+            "create table if not exists skipped (
+             repo_id     bigint,
+             reason      text,
+             skipped_at  datetime,
+             run_id      int,
+             primary key (repo_id),
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists compile_usage (
+             repo_id     bigint,
+             commit_sha  varchar(40),
+             path        text,
+             max_rss     bigint,
+             cpu_time    bigint,
+             run_id      int,
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists repo_logs (
+             repo_id     bigint,
+             commit_sha  varchar(40),
+             path        text,
+             run_id      int,
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists failures (
+             repo_id     bigint,
+             commit_sha  varchar(40),
+             category    varchar(32),
+             n           int,
+             run_id      int,
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists failure_subjects (
+             repo_id     bigint,
+             commit_sha  varchar(40),
+             category    varchar(32),
+             subject     text,
+             n           int,
+             run_id      int,
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists mined_revisions (
+             repo_id       bigint,
+             commit_sha    varchar(40),
+             label         text,
+             committed_at  datetime,
+             n_files       int,
+             n_success     int,
+             n_error       int,
+             time          float,
+             run_id        int,
+             primary key (repo_id, commit_sha),
+             foreign key (repo_id) references repos
+        )"
+        ).execute(&self.pool).await?;
+
+        sqlx::query(
+            "create table if not exists search_progress (
+             min_stars     int,
+             max_stars     int,
+             created_from  varchar(10),
+             created_to    varchar(10),
+             state         varchar(16),
+             pages         int,
+             total         int,
+             primary key (min_stars, max_stars, created_from, created_to)
+        )"
+        ).execute(&self.pool).await?;
+
+        self.migrate().await?;
+
+        return Ok(());
+    }
+
+    /// Add any columns missing from tables created by older versions.
+    async fn migrate(&self) -> Result<(), sqlx::Error> {
+        let columns = [
+            ("repos", "revision", "text"),
+            ("repos", "size", "int"),
+            ("mined", "commit_sha", "varchar(40)"),
+            ("mined", "branch", "text"),
+            ("mined", "cloned_at", "datetime"),
+            ("stats", "peak_disk", "bigint"),
+            ("stats", "partial", "boolean"),
+            ("stats", "n_skipped", "int"),
+            ("stats", "run_id", "int"),
+            ("mined", "run_id", "int"),
+            ("skipped", "run_id", "int"),
+            ("mined_revisions", "run_id", "int"),
+            ("compile_usage", "run_id", "int"),
+            ("repo_logs", "run_id", "int"),
+            ("failures", "run_id", "int"),
+            ("failure_subjects", "run_id", "int"),
+        ];
+
+        for (table, column, kind) in columns {
+            sqlx::query(&format!(
+                "alter table {} add column if not exists {} {}",
+                table, column, kind
+            )).execute(&self.pool).await?;
+        }
+
+        self.widen_repo_ids().await?;
+
+        return Ok(());
+    }
+
+    /// Widen the repo_id columns created as 32-bit ints by older versions, as
+    /// the ids of repositories that don't come from GitHub use 63 bits. This
+    /// includes the tables created by the interface.
+    async fn widen_repo_ids(&self) -> Result<(), sqlx::Error> {
+        let rows = sqlx::query(
+            "select table_name
+             from information_schema.columns
+             where table_schema = database()
+               and column_name = 'repo_id'
+               and data_type = 'int'"
+        ).fetch_all(&self.pool).await?;
+
+        let mut tables = vec![];
+        for row in rows {
+            let table = row.try_get::<&[u8], usize>(0)?;
+            tables.push(String::from_utf8_lossy(table).to_string());
+        }
+        if tables.is_empty() {
+            return Ok(());
+        }
+
+        // The foreign keys only match once every column has been changed, so
+        // they are checked again afterwards instead. Session settings need a
+        // single connection.
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("set foreign_key_checks = 0").execute(conn.as_mut()).await?;
+        for table in &tables {
+            info!("Widening repo_id of '{}' to bigint", table);
+            let result = sqlx::query(&format!("alter table {} modify repo_id bigint", table))
+                .execute(conn.as_mut())
+                .await;
+            if let Err(e) = result {
+                let _ = sqlx::query("set foreign_key_checks = 1").execute(conn.as_mut()).await;
+                return Err(e);
+            }
+        }
+        sqlx::query("set foreign_key_checks = 1").execute(conn.as_mut()).await?;
+
         return Ok(());
     }
 }
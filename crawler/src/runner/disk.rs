@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+/// How long to wait before checking the free space again.
+static SPACE_POLL: Duration = Duration::from_secs(30);
+
+/// Return the disk usage of PATH in bytes.
+pub fn disk_usage(path: &PathBuf) -> u64 {
+    let out = Command::new("du")
+        .arg("-sb")
+        .arg(path)
+        .output();
+
+    match out {
+        Ok(out) => {
+            String::from_utf8_lossy(&out.stdout)
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(0)
+        },
+        Err(e) => {
+            error!("Failed to run du: {}", e);
+            0
+        },
+    }
+}
+
+/// Return the free space on the file system containing PATH in bytes.
+pub fn free_space(path: &PathBuf) -> Option<u64> {
+    let out = match Command::new("df").arg("-Pk").arg(path).output() {
+        Ok(o) => o,
+        Err(e) => {
+            error!("Failed to run df: {}", e);
+            return None;
+        },
+    };
+
+    // The 4th column of the 2nd line is the available space in KB
+    let out = String::from_utf8_lossy(&out.stdout);
+    let kb = out.lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse::<u64>()
+        .ok()?;
+
+    return Some(kb * 1024);
+}
+
+/// Block until the file system containing PATH has at least MIN_FREE bytes
+/// free.
+pub fn wait_for_space(path: &PathBuf, min_free: u64) {
+    let mut warned = false;
+    loop {
+        let Some(free) = free_space(path) else {
+            return;
+        };
+        if free >= min_free {
+            if warned {
+                info!("Free space in {:?} recovered, resuming clones", path);
+            }
+            return;
+        }
+
+        if !warned {
+            warn!("Only {} MB free in {:?}, pausing clones", free / 1024 / 1024, path);
+            warned = true;
+        }
+        thread::sleep(SPACE_POLL);
+    }
+}
+
+/// Delete repositories left in TMP_DIR by a previous run that didn't exit
+/// cleanly, except for those in KEEP.
+pub fn clean_orphans(tmp_dir: &PathBuf, keep: &HashSet<i64>) {
+    let Ok(entries) = fs::read_dir(tmp_dir) else {
+        return;
+    };
+
+    // Repositories are cloned into directories named after their id
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let id = path.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.parse::<i64>().ok());
+        let Some(id) = id else {
+            continue;
+        };
+        if !path.is_dir() || keep.contains(&id) {
+            continue;
+        }
+
+        warn!("Removing orphaned repository at {:?}", path);
+        if let Err(e) = fs::remove_dir_all(&path) {
+            error!("Failed to remove {:?}: {}", path, e);
+        }
+    }
+}
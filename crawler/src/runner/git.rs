@@ -1,6 +1,13 @@
-use std::{path::PathBuf, process::Command};
+use std::{path::{Path, PathBuf}, process::Command};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
 
+use crate::config::{self, Config};
+use crate::metrics;
+use super::mirror::Mirror;
+
+use chrono::Utc;
 use serde_json::value::Value;
 use sqlx::{any::AnyRow, Row};
 use log::{error, info, warn};
@@ -12,12 +19,71 @@ pub struct RepoData {
     pub name: String,
     pub url: String,
     pub stars: i64,
+    pub size: Option<i64>,          // In KB, if known before cloning
+    pub revision: Option<String>,   // Commit or tag to mine, instead of HEAD
 
     // Physical repo
     pub dir: Option<PathBuf>,
+    pub checkout: Option<Checkout>,
+}
+
+/// What was checked out by a clone.
+#[derive(Clone, Debug)]
+pub struct Checkout {
+    pub commit: String,         // SHA of the checked out commit
+    pub branch: Option<String>, // Default branch, or None if a revision was pinned
+    pub cloned_at: String,      // UTC time of the clone
+}
+
+/// How to clone a repository.
+struct CloneOptions {
+    full_history: bool,
+    filter: bool,                   // Only download blobs that are checked out
+    sparse: Option<Vec<String>>,    // Patterns of the files to check out
+    timeout: Option<u64>,           // In seconds
+}
+
+/// Why a repository couldn't be fetched.
+#[derive(Debug)]
+pub enum FetchError {
+    Failed(String),     // The clone or extraction failed
+    TooLarge(u64),      // Larger than `max_repo_size`, in MB
+    Timeout(u64),       // Took longer than `clone_timeout` seconds
+}
+
+impl FetchError {
+    /// Return true if the repository should be recorded as skipped, instead
+    /// of being tried again by the next crawl.
+    pub fn is_skip(&self) -> bool {
+        return !matches!(self, FetchError::Failed(_));
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Failed(e) => write!(f, "{}", e),
+            FetchError::TooLarge(mb) => write!(f, "repository too large ({} MB)", mb),
+            FetchError::Timeout(secs) => write!(f, "clone timed out after {} seconds", secs),
+        }
+    }
 }
 
 impl RepoData {
+    /// Create a repo that hasn't been cloned.
+    pub fn new(id: i64, name: &str, url: &str, stars: i64) -> Self {
+        return Self {
+            id,
+            name: name.to_string(),
+            url: url.to_string(),
+            stars,
+            size: None,
+            revision: None,
+            dir: None,
+            checkout: None,
+        };
+    }
+
     /// Create a repo from JSON.
     pub fn from_json(data: &Value) -> Result<Self, ()> {
         let id    = &data["id"].as_i64().ok_or(())?;
@@ -25,13 +91,9 @@ impl RepoData {
         let url   = &data["clone_url"].as_str().ok_or(())?;
         let stars = &data["stargazers_count"].as_i64().ok_or(())?;
 
-        return Ok(Self {
-            id: *id,
-            name: name.to_string(),
-            url: url.to_string(),
-            stars: *stars,
-            dir: None,
-        });
+        let mut repo = Self::new(*id, name, url, *stars);
+        repo.size = data["size"].as_i64();
+        return Ok(repo);
     }
 
     /// Create a repo from a database row.
@@ -40,45 +102,290 @@ impl RepoData {
         let name  = row.try_get::<&[u8], usize>(1)?;
         let url   = row.try_get::<&[u8], usize>(2)?;
         let stars = row.try_get::<i64, usize>(3)?;
+        let rev   = row.try_get::<Option<&[u8]>, usize>(4)?;
+        let size  = row.try_get::<Option<i64>, usize>(5)?;
 
         let name = String::from_utf8(name.to_vec()).unwrap();
         let url  = String::from_utf8(url.to_vec()).unwrap();
 
-        return Ok(Self { id, name, url, stars, dir: None });
+        let mut repo = Self::new(id, &name, &url, stars);
+        repo.revision = rev.map(|r| String::from_utf8_lossy(r).to_string());
+        repo.size = size;
+        return Ok(repo);
     }
 
-    /// Clone this repo and return the directory cloned to.
-    pub fn git_clone(&mut self, tmp_dir: &PathBuf) -> Result<(), String> {
-        info!("Starting clone of '{}'", self.name);
+    /// Get a copy of the repository in the configured `tmp_dir`, either by
+    /// cloning it or by extracting an archive. If FULL_HISTORY is false, only
+    /// the latest commit is cloned. If MIRROR is given, the clone is made from
+    /// a local mirror.
+    pub fn fetch(&mut self, config: &Config, full_history: bool, mirror: Option<&Mirror>) -> Result<(), FetchError> {
+        let tmp_dir = &config.runner.tmp_dir;
+        if is_archive(&self.url) {
+            return self.extract(tmp_dir).map_err(FetchError::Failed);
+        }
 
-        // If the directory already exists, delete what is there
+        // Don't start cloning repositories that are known to be too large
+        if let (Some(size), Some(max)) = (self.size, config.runner.max_repo_size) {
+            let size = size as u64 / 1024;
+            if size > max {
+                return Err(FetchError::TooLarge(size));
+            }
+        }
+
+        let mut opts = CloneOptions {
+            full_history,
+            filter: config.runner.partial_clone,
+            sparse: match config.runner.partial_clone {
+                true => Some(sparse_patterns(&config.runner.languages)),
+                false => None,
+            },
+            timeout: config.runner.clone_timeout,
+        };
+
+        let url = self.url.clone();
+        let result = match mirror {
+            None => self.git_clone(tmp_dir, &url, &opts),
+            Some(mirror) => match mirror.acquire(self.id, &url) {
+                Ok(path) => {
+                    // The mirror holds the full history, so it is what has to
+                    // fit in the size limit
+                    let size = mirror.size(self.id).unwrap_or(0) / 1024 / 1024;
+                    if let Some(max) = config.runner.max_repo_size.filter(|max| size > *max) {
+                        mirror.release(self.id);
+                        warn!("Mirror of '{}' is {} MB, over the {} MB limit", self.name, size, max);
+                        return Err(FetchError::TooLarge(size));
+                    }
+
+                    // Local clones are cheap, so always clone the full history.
+                    // They don't support filters.
+                    opts.full_history = true;
+                    opts.filter = false;
+                    let result = self.git_clone(tmp_dir, path.to_str().unwrap(), &opts);
+                    mirror.release(self.id);
+
+                    // Relative submodule URLs are resolved against origin
+                    if let (Ok(_), Some(dir)) = (&result, &self.dir) {
+                        let _ = git(Some(dir), &["remote", "set-url", "origin", &url]);
+                    }
+                    result
+                },
+                // Cloning from the network would take at least as long
+                Err(e) if e == TIMED_OUT => {
+                    return Err(FetchError::Timeout(opts.timeout.unwrap_or(0)));
+                },
+                // Fall back to the network if the mirror can't be updated
+                Err(_) => self.git_clone(tmp_dir, &url, &opts),
+            },
+        };
+        return result;
+    }
+
+    /// Return the directory to place this repo in, removing any old files.
+    fn fresh_dir(&self, tmp_dir: &Path) -> PathBuf {
         let dir = tmp_dir.join(format!("{}", self.id));
         if dir.exists() {
             warn!("Removing pre-existing files at: {:?}", dir);
             let _ = fs::remove_dir_all(&dir);
         }
+        return dir;
+    }
 
-        // Clone the repo
-        let out = Command::new("git")
-            .arg("clone")
-            .arg(&self.url)
-            .arg("--depth")
-            .arg("1")
-            .arg(&dir)
-            .output()
-            .expect("Failed to execute git");
+    /// Extract this repo's archive.
+    pub fn extract(&mut self, tmp_dir: &Path) -> Result<(), String> {
+        info!("Starting extraction of '{}'", self.name);
+
+        let dir = self.fresh_dir(tmp_dir);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("Failed to create {:?}: {}", dir, e);
+            return Err(e.to_string());
+        }
+
+        // The directory is deleted on drop, even if extraction fails
+        self.dir = Some(dir.clone());
+
+        // Tar detects the compression itself
+        let out = if self.url.ends_with(".zip") {
+            Command::new("unzip")
+                .arg("-q")
+                .arg(&self.url)
+                .arg("-d")
+                .arg(&dir)
+                .output()
+        } else {
+            Command::new("tar")
+                .arg("-xf")
+                .arg(&self.url)
+                .arg("-C")
+                .arg(&dir)
+                .output()
+        };
+
+        let out = match out {
+            Ok(o) => o,
+            Err(e) => {
+                error!("Failed to run extraction for '{}': {}", self.name, e);
+                return Err(e.to_string());
+            },
+        };
 
-        // Error if there is a non-zero exit code
         if out.status.success() {
-            info!("Finished cloning '{}' to {:?}", self.name, dir);
-            self.dir = Some(dir);
+            info!("Finished extracting '{}' to {:?}", self.name, dir);
             return Ok(());
         } else {
-            let err = String::from_utf8(out.stderr).unwrap();
-            error!("Failed to clone '{}': {}", self.name, err);
+            let err = String::from_utf8_lossy(&out.stderr).to_string();
+            error!("Failed to extract '{}': {}", self.name, err);
             return Err(err);
         }
     }
+
+    /// Clone this repo from SOURCE and return the directory cloned to.
+    ///
+    /// If a revision is pinned, only that revision is fetched.
+    fn git_clone(&mut self, tmp_dir: &Path, source: &str, opts: &CloneOptions) -> Result<(), FetchError> {
+        info!("Starting clone of '{}'", self.name);
+
+        // If the directory already exists, delete what is there
+        let dir = self.fresh_dir(tmp_dir);
+        let cloned_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+        // Arguments shared by clone & fetch
+        let mut extra = vec![];
+        if opts.filter {
+            extra.push("--filter=blob:none");
+        }
+        if !opts.full_history || self.revision.is_some() {
+            extra.extend(["--depth", "1"]);
+        }
+
+        // Clone the repo
+        let timeout = opts.timeout;
+        let result = match &self.revision {
+            None => {
+                let mut args = vec!["clone", source, dir.to_str().unwrap()];
+                args.extend(&extra);
+                if opts.sparse.is_some() {
+                    args.push("--no-checkout");
+                }
+                git_timeout(None, &args, timeout)
+                    .and_then(|_| self.sparse_checkout(&dir, opts))
+                    .and_then(|_| match opts.sparse {
+                        Some(_) => git_timeout(Some(&dir), &["checkout", "--quiet"], timeout),
+                        None => Ok(String::new()),
+                    })
+            },
+            Some(rev) => {
+                info!("Fetching revision '{}' of '{}'", rev, self.name);
+                let _ = fs::create_dir_all(&dir);
+                let mut args = vec!["fetch", "origin", rev.as_str()];
+                args.extend(&extra);
+                git(Some(&dir), &["init", "--quiet"])
+                    .and_then(|_| self.sparse_checkout(&dir, opts))
+                    .and_then(|_| git(Some(&dir), &["remote", "add", "origin", source]))
+                    .and_then(|_| git_timeout(Some(&dir), &args, timeout))
+                    .and_then(|_| git_timeout(Some(&dir), &["checkout", "--quiet", "FETCH_HEAD"], timeout))
+            },
+        };
+
+        // Error if there is a non-zero exit code
+        if let Err(err) = result {
+            error!("Failed to clone '{}': {}", self.name, err);
+            let _ = fs::remove_dir_all(&dir);
+            return match timeout {
+                Some(secs) if err == TIMED_OUT => Err(FetchError::Timeout(secs)),
+                _ => Err(FetchError::Failed(err)),
+            };
+        }
+        info!("Finished cloning '{}' to {:?}", self.name, dir);
+
+        // Record what was checked out
+        let commit = git(Some(&dir), &["rev-parse", "HEAD"]);
+        let branch = match &self.revision {
+            None => git(Some(&dir), &["rev-parse", "--abbrev-ref", "HEAD"]).ok(),
+            Some(_) => None,
+        };
+        match commit {
+            Ok(commit) => {
+                info!("Checked out '{}' at {}", self.name, commit);
+                self.checkout = Some(Checkout { commit, branch, cloned_at });
+            },
+            Err(e) => warn!("Failed to find HEAD of '{}': {}", self.name, e),
+        }
+
+        self.dir = Some(dir);
+        return Ok(());
+    }
+
+    /// Limit the files checked out in DIR to the sparse patterns in OPTS.
+    fn sparse_checkout(&self, dir: &PathBuf, opts: &CloneOptions) -> Result<String, String> {
+        let Some(patterns) = &opts.sparse else {
+            return Ok(String::new());
+        };
+
+        let mut args = vec!["sparse-checkout", "set", "--no-cone"];
+        args.extend(patterns.iter().map(|p| p.as_str()));
+        return git(Some(dir), &args);
+    }
+}
+
+/// Error returned by `git_timeout` when git is killed.
+pub static TIMED_OUT: &str = "timed out";
+
+/// Return the patterns of the files of LANGUAGES, for a sparse checkout.
+fn sparse_patterns(languages: &HashSet<String>) -> Vec<String> {
+    let mut acc: Vec<String> = languages.iter()
+        .filter_map(|l| config::language_patterns(l))
+        .flatten()
+        .map(|p| p.to_string())
+        .collect();
+
+    // Headers are shared by C & C++
+    acc.sort();
+    acc.dedup();
+    return acc;
+}
+
+/// Run git with ARGS in DIR, & return the trimmed output.
+pub fn git(dir: Option<&PathBuf>, args: &[&str]) -> Result<String, String> {
+    return git_timeout(dir, args, None);
+}
+
+/// Run git with ARGS in DIR, killing it after TIMEOUT seconds.
+pub fn git_timeout(dir: Option<&PathBuf>, args: &[&str], timeout: Option<u64>) -> Result<String, String> {
+    let mut cmd = match timeout {
+        Some(secs) => {
+            let mut cmd = Command::new("timeout");
+            cmd.arg("--kill-after=10").arg(secs.to_string()).arg("git");
+            cmd
+        },
+        None => Command::new("git"),
+    };
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+
+    let out = cmd.args(args)
+        .output()
+        .expect("Failed to execute git");
+
+    // Timeout exits with 124 if the command timed out, or 137 if it had to
+    // be killed
+    let code = out.status.code();
+    if timeout.is_some() && (code == Some(124) || code == Some(137)) {
+        metrics::TIMEOUTS.inc();
+        return Err(TIMED_OUT.to_string());
+    }
+
+    if out.status.success() {
+        return Ok(String::from_utf8_lossy(&out.stdout).trim().to_string());
+    } else {
+        return Err(String::from_utf8_lossy(&out.stderr).to_string());
+    }
+}
+
+/// Return true if URL points to a source archive instead of a repository.
+pub fn is_archive(url: &str) -> bool {
+    let exts = [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".zip"];
+    return exts.iter().any(|e| url.ends_with(e));
 }
 
 /// Delete the Repository files when the repo goes out of scope
@@ -90,3 +397,23 @@ impl Drop for RepoData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn languages(names: &[&str]) -> HashSet<String> {
+        return names.iter().map(|n| n.to_string()).collect();
+    }
+
+    #[test]
+    fn sparse_patterns_of_c() {
+        assert_eq!(sparse_patterns(&languages(&["c"])), ["*.c", "*.h"]);
+    }
+
+    #[test]
+    fn sparse_patterns_share_headers() {
+        let patterns = sparse_patterns(&languages(&["c", "cpp"]));
+        assert_eq!(patterns, ["*.c", "*.cc", "*.cpp", "*.cxx", "*.h", "*.hh", "*.hpp"]);
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use super::tokens::TokenPool;
+
+use reqwest::blocking::Client;
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use serde_json::value::Value;
+use log::{info, warn};
+
+static USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+);
+
+static INITIAL_BACKOFF: f64 = 1.0;      // Seconds
+static MAX_BACKOFF: f64 = 300.0;        // Seconds
+static SECONDARY_WAIT: f64 = 60.0;      // Minimum wait after a secondary limit
+
+/// Errors returned by the GitHub client.
+#[derive(Debug)]
+pub enum ClientError {
+    Build(reqwest::Error),          // Failed to create the client
+    Network(reqwest::Error),        // Request failed after all retries
+    Status(StatusCode, String),     // Non-retryable status, with the message
+    Parse(serde_json::Error),       // Response isn't valid JSON
+    Missing(&'static str),          // Response is missing a required field
+    Retries(StatusCode),            // Ran out of retries, with the last status
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Build(e)   => write!(f, "failed to build client: {}", e),
+            ClientError::Network(e) => write!(f, "network error: {}", e),
+            ClientError::Status(s, m) => write!(f, "{}: {}", s, m),
+            ClientError::Parse(e)   => write!(f, "invalid JSON: {}", e),
+            ClientError::Missing(k) => write!(f, "response is missing '{}'", k),
+            ClientError::Retries(s) => write!(f, "gave up after retries, last status: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// What to do after a failed request.
+enum Retry {
+    Rotate,         // Retry straight away, the token pool decides when
+    Backoff,        // Retry after an exponential backoff
+    Never,          // Don't retry
+}
+
+/// GitHub REST API client that handles rate limits & transient failures.
+pub struct GitHubClient {
+    client: Client,
+    tokens: TokenPool,
+    base_url: String,
+    max_retries: usize,
+}
+
+impl GitHubClient {
+    /// Create a new client using CONFIG.
+    pub fn new(config: &Config) -> Result<Self, ClientError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-GitHub-Api-Version",
+            header::HeaderValue::from_static("2022-11-28")
+        );
+
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .default_headers(headers)
+            .build()
+            .map_err(ClientError::Build)?;
+
+        let tokens = TokenPool::new(config);
+        let base_url = config.search.api_base_url.trim_end_matches('/').to_string();
+        let max_retries = config.search.max_retries;
+
+        return Ok(Self { client, tokens, base_url, max_retries });
+    }
+
+    /// Search for repositories with the query string QUERY.
+    pub fn search_repositories(&self, query: &str) -> Result<Value, ClientError> {
+        let json = self.get(&format!("/search/repositories?{}", query))?;
+
+        // A successful search always contains the items
+        if json["items"].is_null() {
+            return Err(ClientError::Missing("items"));
+        }
+
+        return Ok(json);
+    }
+
+    /// Get PATH from the API & parse the result as JSON, retrying on
+    /// transient failures.
+    pub fn get(&self, path: &str) -> Result<Value, ClientError> {
+        // The URL never contains a token, so it is safe to log
+        let url = format!("{}{}", self.base_url, path);
+        let mut backoff = INITIAL_BACKOFF;
+        let mut failures = 0;      // Rate limits aren't counted as failures
+        let mut rotations = 0;     // Rate limits, which may be one per token
+        let max_rotations = self.max_retries + self.tokens.len();
+
+        loop {
+            info!("{:#?}", url);
+
+            // Send the request with the least used token
+            let (index, token) = self.tokens.acquire();
+            let mut request = self.client.get(&url);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+
+            let retry = match request.send() {
+                Ok(response) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    self.tokens.update(index, &headers);
+
+                    if status.is_success() {
+                        let text = response.text().map_err(ClientError::Network)?;
+                        return serde_json::from_str(&text).map_err(ClientError::Parse);
+                    }
+
+                    let message = response.text().unwrap_or_default();
+                    let retry = self.retry(index, status, &headers, &message);
+                    warn!("Request failed with {}: {}", status, message);
+
+                    match retry {
+                        Retry::Never => {
+                            return Err(ClientError::Status(status, message));
+                        },
+                        Retry::Backoff if failures >= self.max_retries => {
+                            return Err(ClientError::Retries(status));
+                        },
+                        Retry::Rotate if rotations >= max_rotations => {
+                            return Err(ClientError::Retries(status));
+                        },
+                        _ => retry,
+                    }
+                },
+                Err(e) => {
+                    warn!("Request failed: {}", e);
+                    if failures >= self.max_retries {
+                        return Err(ClientError::Network(e));
+                    }
+                    Retry::Backoff
+                },
+            };
+
+            // Sleep before the next attempt
+            if let Retry::Rotate = retry {
+                rotations += 1;
+            }
+            if let Retry::Backoff = retry {
+                failures += 1;
+                info!("Retrying in {} seconds", backoff);
+                thread::sleep(Duration::from_secs_f64(backoff));
+                backoff = (backoff * 2.0).min(MAX_BACKOFF);
+            }
+        }
+    }
+
+    /// Decide whether to retry a failed response using token INDEX.
+    fn retry(&self, index: usize, status: StatusCode, headers: &HeaderMap, message: &str) -> Retry {
+        // Server errors are usually transient
+        if status.is_server_error() {
+            return Retry::Backoff;
+        }
+
+        // Rate limits are reported as either 403 or 429
+        if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+            // Secondary rate limits give the time to wait
+            if let Some(time) = from_header::<f64>(headers, "retry-after") {
+                self.tokens.suspend(index, time);
+                return Retry::Rotate;
+            }
+
+            // Primary rate limit, the pool waits until the limit resets
+            if self.tokens.exhausted(index) {
+                return Retry::Rotate;
+            }
+
+            // Secondary rate limit without a time, wait at least a minute
+            if status == StatusCode::TOO_MANY_REQUESTS
+                || message.contains("secondary rate limit") {
+                self.tokens.suspend(index, SECONDARY_WAIT);
+                return Retry::Rotate;
+            }
+        }
+
+        return Retry::Never;
+    }
+}
+
+/// Return TIME as an integer (unix time).
+pub fn parse_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Get a NAME from HEADERS & parse it to a given type.
+pub fn from_header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?
+        .to_str()
+        .ok()?
+        .parse::<T>()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::mock::{self, MockServer, Response};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Create a client for SERVER that retries up to MAX_RETRIES times.
+    fn client(server: &MockServer, max_retries: usize) -> GitHubClient {
+        let config = mock::config(&format!(
+            "[search]\napi_base_url = \"{}\"\nmax_retries = {}\n",
+            server.url, max_retries
+        ));
+        return GitHubClient::new(&config).unwrap();
+    }
+
+    /// Start a server that answers the first request with FIRST, & every
+    /// later request with an empty object.
+    fn fail_once(first: fn() -> Response) -> MockServer {
+        let count = AtomicUsize::new(0);
+        return MockServer::start(move |_| {
+            match count.fetch_add(1, Ordering::SeqCst) {
+                0 => first(),
+                _ => Response::json("{}".to_string()),
+            }
+        });
+    }
+
+    fn status(status: u16, headers: Vec<(&'static str, String)>) -> Response {
+        return Response { status, headers, body: "{}".to_string() };
+    }
+
+    #[test]
+    fn retry_server_error() {
+        let server = fail_once(|| status(502, vec![]));
+        assert!(client(&server, 1).get("/rate_limit").is_ok());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn retry_after_forbidden() {
+        let server = fail_once(|| status(403, vec![("Retry-After", "0".to_string())]));
+        assert!(client(&server, 0).get("/rate_limit").is_ok());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn retry_after_too_many_requests() {
+        let server = fail_once(|| status(429, vec![("Retry-After", "0".to_string())]));
+        assert!(client(&server, 0).get("/rate_limit").is_ok());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn give_up_after_retries() {
+        let server = MockServer::start(|_| status(500, vec![]));
+        let result = client(&server, 2).get("/rate_limit");
+        assert!(matches!(result, Err(ClientError::Retries(StatusCode::INTERNAL_SERVER_ERROR))));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn give_up_after_rate_limits() {
+        // One rotation for each retry & token
+        let server = MockServer::start(|_| status(429, vec![("Retry-After", "0".to_string())]));
+        let result = client(&server, 2).get("/rate_limit");
+        assert!(matches!(result, Err(ClientError::Retries(StatusCode::TOO_MANY_REQUESTS))));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn client_error_isnt_retried() {
+        let server = MockServer::start(|_| status(404, vec![]));
+        let result = client(&server, 2).get("/rate_limit");
+        assert!(matches!(result, Err(ClientError::Status(StatusCode::NOT_FOUND, _))));
+        assert_eq!(server.requests().len(), 1);
+
+        // Forbidden without a rate limit is a permissions problem
+        let server = MockServer::start(|_| status(403, vec![]));
+        assert!(matches!(client(&server, 2).get("/rate_limit"), Err(ClientError::Status(..))));
+        assert_eq!(server.requests().len(), 1);
+    }
+}
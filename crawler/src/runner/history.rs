@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::config::{History, HistoryMode};
+use super::git::{git, git_timeout};
+
+use chrono::{Datelike, Local};
+use log::{debug, warn};
+
+/// A single revision of a repository to mine.
+#[derive(Clone, Debug)]
+pub struct Revision {
+    pub commit: String,
+    pub time: i64,          // Committer date, as unix time
+    pub label: String,      // Year or tag name
+}
+
+/// Return the revisions of the repository in DIR to mine, oldest first.
+pub fn revisions(dir: &PathBuf, history: &History) -> Vec<Revision> {
+    let candidates = match history.mode {
+        HistoryMode::Yearly => yearly(dir),
+        HistoryMode::Tags => tags(dir),
+    };
+
+    // Remove revisions that point to the same commit
+    let mut seen = HashSet::new();
+    let mut acc: Vec<Revision> = vec![];
+    for (label, commit) in candidates {
+        if seen.contains(&commit) {
+            continue;
+        }
+        seen.insert(commit.clone());
+
+        let time = git(Some(dir), &["show", "-s", "--format=%ct", &commit])
+            .ok()
+            .and_then(|t| t.parse::<i64>().ok())
+            .unwrap_or(0);
+        acc.push(Revision { commit, time, label });
+    }
+
+    // Keep the most recent revisions
+    if acc.len() > history.max_revisions {
+        acc.drain(..acc.len() - history.max_revisions);
+    }
+
+    return acc;
+}
+
+/// Return the last commit of each year, from the first commit until now.
+fn yearly(dir: &PathBuf) -> Vec<(String, String)> {
+    let Some(first) = first_year(dir) else {
+        warn!("Failed to find the first commit in {:?}", dir);
+        return vec![];
+    };
+
+    let mut acc = vec![];
+    for year in first..=Local::now().year() {
+        let before = format!("--before={}-12-31 23:59:59", year);
+        match git(Some(dir), &["rev-list", "-1", &before, "HEAD"]) {
+            Ok(commit) if !commit.is_empty() => {
+                acc.push((year.to_string(), commit));
+            },
+            _ => debug!("No commit for {} in {:?}", year, dir),
+        }
+    }
+
+    return acc;
+}
+
+/// Return the year of the earliest root commit.
+fn first_year(dir: &PathBuf) -> Option<i32> {
+    let roots = git(Some(dir), &["rev-list", "--max-parents=0", "HEAD"]).ok()?;
+
+    roots.lines()
+        .filter_map(|root| {
+            git(Some(dir), &["show", "-s", "--format=%cd", "--date=format:%Y", root]).ok()
+        })
+        .filter_map(|year| year.parse::<i32>().ok())
+        .min()
+}
+
+/// Return the commit of each tag, in order of creation.
+fn tags(dir: &PathBuf) -> Vec<(String, String)> {
+    let names = match git(Some(dir), &["tag", "--sort=creatordate"]) {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Failed to list tags in {:?}: {}", dir, e);
+            return vec![];
+        },
+    };
+
+    let mut acc = vec![];
+    for tag in names.lines() {
+        match git(Some(dir), &["rev-list", "-n", "1", tag]) {
+            Ok(commit) => acc.push((tag.to_string(), commit)),
+            Err(e) => warn!("Failed to resolve tag '{}': {}", tag, e),
+        }
+    }
+
+    return acc;
+}
+
+/// Check out COMMIT in the repository in DIR, giving up after TIMEOUT
+/// seconds. Partial clones fetch the files of COMMIT over the network.
+pub fn checkout(dir: &PathBuf, commit: &str, timeout: Option<u64>) -> Result<(), String> {
+    git_timeout(Some(dir), &["checkout", "--quiet", "--force", commit], timeout)?;
+    return Ok(());
+}
+
+/// Return the files that differ between commits FROM & TO, giving up after
+/// TIMEOUT seconds. Renames aren't detected, as that would fetch the files of
+/// partial clones.
+pub fn changed_files(dir: &PathBuf, from: &str, to: &str, timeout: Option<u64>) -> Option<HashSet<PathBuf>> {
+    let args = ["diff", "--name-only", "--no-renames", from, to];
+    match git_timeout(Some(dir), &args, timeout) {
+        Ok(out) => Some(out.lines().map(PathBuf::from).collect()),
+        Err(e) => {
+            warn!("Failed to diff {} & {}: {}", from, to, e);
+            None
+        },
+    }
+}
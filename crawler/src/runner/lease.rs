@@ -0,0 +1,355 @@
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::config::Config;
+use super::db::Database;
+use super::git::RepoData;
+
+use sqlx::{self, Any, Row};
+use log::{debug, error, info};
+
+/// Number of candidate repositories to fetch at once.
+static BATCH_SIZE: i64 = 32;
+
+/// Claims on repositories, so several workers can share one database without
+/// mining the same repository twice.
+///
+/// Each claim expires unless it is renewed, so the repositories of a worker
+/// that crashed can be claimed by another.
+pub struct Leases<'a> {
+    db: &'a Database,
+    worker: String,
+    duration: u64,              // Seconds until a claim expires
+    cursor: Mutex<i64>,         // Largest repo_id tried since the last rescan
+    active: Mutex<HashSet<i64>>,
+    tried: Mutex<HashSet<i64>>, // Claimed by this worker, never claimed again
+}
+
+impl<'a> Leases<'a> {
+    pub fn new(config: &Config, db: &'a Database) -> Self {
+        let worker = match &config.runner.worker_id {
+            Some(id) => id.clone(),
+            None => default_worker_id(),
+        };
+
+        return Self {
+            db,
+            worker,
+            duration: config.runner.lease_time,
+            cursor: Mutex::new(i64::MIN),
+            active: Default::default(),
+            tried: Default::default(),
+        };
+    }
+
+    /// Return the id of this worker.
+    pub fn worker(&self) -> &str {
+        return &self.worker;
+    }
+
+    /// Claim the next repository that hasn't been mined, skipped, or claimed
+    /// by another worker. Returns None once there are no repositories left.
+    ///
+    /// Repositories are tried in order of id. Once there are none left, the
+    /// claims that expired behind the cursor, such as those of a worker that
+    /// crashed, are tried again.
+    pub fn claim_next(&self) -> Option<RepoData> {
+        let mut cursor = self.cursor.lock().unwrap();
+        let mut rescanned = None;   // Claim the last rescan started from
+
+        loop {
+            let candidates = match self.db.rt.block_on(self.candidates(*cursor)) {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Failed to fetch repositories: {:?}", e);
+                    return None;
+                },
+            };
+            if candidates.is_empty() {
+                // Don't rescan from a claim that couldn't be taken
+                match self.db.rt.block_on(self.lowest_expired(*cursor)) {
+                    Ok(Some(id)) if rescanned != Some(id) => {
+                        rescanned = Some(id);
+                        info!("Rescanning from expired claim on {}", id);
+                        *cursor = id - 1;
+                        continue;
+                    },
+                    Ok(_) => return None,
+                    Err(e) => {
+                        error!("Failed to fetch expired claims: {:?}", e);
+                        return None;
+                    },
+                }
+            }
+
+            for repo in candidates {
+                *cursor = repo.id;
+
+                // A rescan passes the repos this worker already gave up on
+                if self.tried.lock().unwrap().contains(&repo.id) {
+                    continue;
+                }
+
+                match self.db.rt.block_on(self.claim(repo.id)) {
+                    Ok(true) => {
+                        debug!("Claimed '{}'", repo.name);
+                        self.active.lock().unwrap().insert(repo.id);
+                        self.tried.lock().unwrap().insert(repo.id);
+                        return Some(repo);
+                    },
+                    Ok(false) => debug!("'{}' is claimed by another worker", repo.name),
+                    Err(e) => error!("Failed to claim '{}': {:?}", repo.name, e),
+                }
+            }
+        }
+    }
+
+    /// Release the claim on repo ID.
+    pub fn release(&self, id: i64) {
+        self.active.lock().unwrap().remove(&id);
+
+        let result = self.db.rt.block_on(
+            sqlx::query::<Any>("delete from claims where repo_id = ? and worker_id = ?")
+                .bind(id)
+                .bind(self.worker.clone())
+                .execute(&self.db.pool)
+        );
+
+        if let Err(e) = result {
+            error!("Failed to release claim on {}: {:?}", id, e);
+        }
+    }
+
+    /// Renew the active claims regularly, until STOP is dropped.
+    pub fn renew_until(&self, stop: Receiver<()>) {
+        let interval = Duration::from_secs((self.duration / 3).max(1));
+        loop {
+            match stop.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => self.renew(),
+                _ => return,
+            }
+        }
+    }
+
+    /// Extend the expiry of every active claim.
+    fn renew(&self) {
+        let active: Vec<i64> = self.active.lock().unwrap().iter().cloned().collect();
+        for id in active {
+            let result = self.db.rt.block_on(
+                sqlx::query::<Any>(
+                    "update claims set expires_at = now() + interval ? second
+                     where repo_id = ? and worker_id = ?"
+                ).bind(self.duration as i64)
+                    .bind(id)
+                    .bind(self.worker.clone())
+                    .execute(&self.db.pool)
+            );
+
+            if let Err(e) = result {
+                error!("Failed to renew claim on {}: {:?}", id, e);
+            }
+        }
+    }
+
+    /// Return the repositories claimed by any worker that haven't expired.
+    pub fn claimed(&self) -> HashSet<i64> {
+        let rows = self.db.rt.block_on(
+            sqlx::query::<Any>("select repo_id from claims where expires_at > now()")
+                .fetch_all(&self.db.pool)
+        );
+
+        match rows {
+            Ok(rows) => rows.iter().filter_map(|r| r.try_get::<i64, usize>(0).ok()).collect(),
+            Err(e) => {
+                error!("Failed to fetch claims: {:?}", e);
+                HashSet::new()
+            },
+        }
+    }
+
+    /// Return the next repositories after CURSOR that can be claimed.
+    async fn candidates(&self, cursor: i64) -> Result<Vec<RepoData>, sqlx::Error> {
+        let rows = sqlx::query::<Any>(
+            "select repo_id, name, clone_url, stars, revision, size
+             from repos
+             where repo_id > ?
+               and repo_id not in (select repo_id from mined)
+               and repo_id not in (select repo_id from skipped)
+               and repo_id not in (select repo_id from claims where expires_at > now())
+             order by repo_id
+             limit ?"
+        ).bind(cursor)
+            .bind(BATCH_SIZE)
+            .fetch_all(&self.db.pool)
+            .await?;
+
+        let mut acc = vec![];
+        for row in rows {
+            acc.push(RepoData::from_row(row)?);
+        }
+
+        return Ok(acc);
+    }
+
+    /// Return the lowest repo_id up to CURSOR with an expired claim, whose
+    /// repository hasn't been mined or skipped.
+    async fn lowest_expired(&self, cursor: i64) -> Result<Option<i64>, sqlx::Error> {
+        let row = sqlx::query::<Any>(
+            "select min(repo_id)
+             from claims
+             where repo_id <= ?
+               and expires_at <= now()
+               and repo_id in (select repo_id from repos)
+               and repo_id not in (select repo_id from mined)
+               and repo_id not in (select repo_id from skipped)"
+        ).bind(cursor)
+            .fetch_one(&self.db.pool)
+            .await?;
+
+        return row.try_get::<Option<i64>, usize>(0);
+    }
+
+    /// Try to claim repo ID, taking over the claim if it has expired. Returns
+    /// true if this worker now holds the claim.
+    async fn claim(&self, id: i64) -> Result<bool, sqlx::Error> {
+        // Columns are updated in order, so both conditions see the old expiry
+        sqlx::query::<Any>(
+            "insert into claims (repo_id, worker_id, expires_at)
+             values (?, ?, now() + interval ? second)
+             on duplicate key update
+               worker_id  = if(expires_at < now(), values(worker_id), worker_id),
+               expires_at = if(expires_at < now(), values(expires_at), expires_at)"
+        ).bind(id)
+            .bind(self.worker.clone())
+            .bind(self.duration as i64)
+            .execute(&self.db.pool)
+            .await?;
+
+        let row = sqlx::query::<Any>("select worker_id from claims where repo_id = ?")
+            .bind(id)
+            .fetch_one(&self.db.pool)
+            .await?;
+        let owner = row.try_get::<&[u8], usize>(0)?;
+
+        return Ok(owner == self.worker.as_bytes());
+    }
+}
+
+/// Return a worker id that is unique to this process.
+fn default_worker_id() -> String {
+    let host = fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string());
+
+    let id = format!("{}-{}", host, std::process::id());
+    info!("Using worker id '{}'", id);
+    return id;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mock;
+    use super::super::source::add_repos;
+
+    /// Ids of the repositories claimed by the tests.
+    const FIRST_ID: i64 = 1_800_000_000;
+
+    fn leases<'a>(config: &Config, db: &'a Database, worker: &str) -> Leases<'a> {
+        let mut config = config.clone();
+        config.runner.worker_id = Some(worker.to_string());
+        return Leases::new(&config, db);
+    }
+
+    /// Return the worker holding the claim on repo ID, & the seconds until it
+    /// expires.
+    fn claim_of(db: &Database, id: i64) -> Option<(String, i64)> {
+        let row = db.rt.block_on(
+            sqlx::query::<Any>(
+                "select worker_id, cast(timestampdiff(second, now(), expires_at) as signed)
+                 from claims where repo_id = ?"
+            ).bind(id)
+                .fetch_optional(&db.pool)
+        ).unwrap()?;
+
+        let worker = String::from_utf8_lossy(row.get::<&[u8], usize>(0)).to_string();
+        return Some((worker, row.get::<i64, usize>(1)));
+    }
+
+    /// Add repos FIRST_ID + 1 & FIRST_ID + 2, without any claims.
+    fn setup(db: &Database) {
+        for table in ["claims", "repos"] {
+            db.rt.block_on(
+                sqlx::query::<Any>(&format!(
+                    "delete from {} where repo_id > ? and repo_id <= ?", table
+                )).bind(FIRST_ID)
+                    .bind(FIRST_ID + 2)
+                    .execute(&db.pool)
+            ).unwrap();
+        }
+
+        let repos = (1..=2)
+            .map(|i| RepoData::new(FIRST_ID + i, &format!("mock/lease-{}", i), "https://example.com", 20))
+            .collect();
+        add_repos(db, repos);
+    }
+
+    #[test]
+    fn claims_are_exclusive_until_expired() {
+        let config = mock::config("");
+        let Some(db) = mock::database(&config) else {
+            return;
+        };
+        setup(&db);
+        let (a, b) = (leases(&config, &db, "worker-a"), leases(&config, &db, "worker-b"));
+        let id = FIRST_ID + 1;
+
+        assert!(db.rt.block_on(a.claim(id)).unwrap());
+        assert!(!db.rt.block_on(b.claim(id)).unwrap());
+        assert_eq!(claim_of(&db, id).unwrap().0, "worker-a");
+
+        // Worker A crashed, so its claim expired
+        db.rt.block_on(
+            sqlx::query::<Any>("update claims set expires_at = now() - interval 1 second where repo_id = ?")
+                .bind(id)
+                .execute(&db.pool)
+        ).unwrap();
+        assert!(db.rt.block_on(b.claim(id)).unwrap());
+        assert!(!db.rt.block_on(a.claim(id)).unwrap());
+        assert_eq!(claim_of(&db, id).unwrap().0, "worker-b");
+
+        // Only the holder can release the claim
+        a.release(id);
+        assert!(claim_of(&db, id).is_some());
+        b.release(id);
+        assert!(claim_of(&db, id).is_none());
+    }
+
+    #[test]
+    fn renew_active_claims() {
+        let config = mock::config("");
+        let Some(db) = mock::database(&config) else {
+            return;
+        };
+        setup(&db);
+        let a = leases(&config, &db, "worker-a");
+        let id = FIRST_ID + 2;
+
+        assert!(db.rt.block_on(a.claim(id)).unwrap());
+        db.rt.block_on(
+            sqlx::query::<Any>("update claims set expires_at = now() + interval 1 second where repo_id = ?")
+                .bind(id)
+                .execute(&db.pool)
+        ).unwrap();
+
+        a.active.lock().unwrap().insert(id);
+        a.renew();
+        let (worker, left) = claim_of(&db, id).unwrap();
+        assert_eq!(worker, "worker-a");
+        assert!(left > 1 && left <= a.duration as i64);
+        a.release(id);
+    }
+}
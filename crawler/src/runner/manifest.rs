@@ -0,0 +1,107 @@
+use crate::config::Config;
+use crate::interface::AnyInterface;
+use super::db::Database;
+
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{self, Any};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A row of the `runs` table, describing the setup of a single `crawl` or
+/// `mine`. Rows added by the run reference its id, so results can be traced
+/// back to the versions & config that produced them.
+pub struct Manifest {
+    pub id: i64,
+}
+
+impl Manifest {
+    /// Record the start of COMMAND, run with CONFIG & INTERFACE.
+    pub fn start(command: &str, config: &Config, db: &Database, interface: &AnyInterface) -> Self {
+        let provenance = interface.provenance();
+
+        // Record what each tool reports, in case it changes between runs
+        let mut tools = Map::new();
+        for tool in &provenance.tools {
+            tools.insert(tool.to_string_lossy().to_string(), json!(tool_version(tool)));
+        }
+        let mut plugins = Map::new();
+        for plugin in &provenance.plugins {
+            plugins.insert(plugin.to_string_lossy().to_string(), json!(file_hash(plugin)));
+        }
+
+        let config = serde_json::to_string(config).expect("Failed to serialize config");
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|h| h.trim().to_string())
+            .unwrap_or_else(|_| "localhost".to_string());
+
+        let result = db.rt.block_on(
+            sqlx::query::<Any>(
+                "insert into runs
+                 (command, crawler_version, interface, interface_version,
+                  tools, plugins, config, hostname, started_at)
+                 values (?, ?, ?, ?, ?, ?, ?, ?, utc_timestamp())"
+            ).bind(command)
+                .bind(env!("CARGO_PKG_VERSION"))
+                .bind(provenance.name)
+                .bind(provenance.version)
+                .bind(Value::Object(tools).to_string())
+                .bind(Value::Object(plugins).to_string())
+                .bind(config)
+                .bind(hostname)
+                .execute(&db.pool)
+        );
+
+        let id = match result.map(|r| r.last_insert_id()) {
+            Ok(Some(id)) => id,
+            Ok(None) => panic!("Failed to record run: no id was returned"),
+            Err(e) => panic!("Failed to record run: {:?}", e),
+        };
+        info!("Recorded as run {}", id);
+
+        return Self { id };
+    }
+
+    /// Record the end of the run.
+    pub fn finish(&self, db: &Database) {
+        let result = db.rt.block_on(
+            sqlx::query::<Any>("update runs set ended_at = utc_timestamp() where run_id = ?")
+                .bind(self.id)
+                .execute(&db.pool)
+        );
+
+        if let Err(e) = result {
+            warn!("Failed to record the end of run {}: {:?}", self.id, e);
+        }
+    }
+}
+
+/// Return the `--version` output of TOOL, or None if it couldn't be run.
+fn tool_version(tool: &PathBuf) -> Option<String> {
+    match Command::new(tool).arg("--version").output() {
+        Ok(o) if o.status.success() => {
+            Some(String::from_utf8_lossy(&o.stdout).trim().to_string())
+        },
+        Ok(o) => {
+            warn!("{:?} --version exited with {}", tool, o.status);
+            None
+        },
+        Err(e) => {
+            warn!("Failed to run {:?} --version: {}", tool, e);
+            None
+        },
+    }
+}
+
+/// Return the SHA-256 of the file at PATH, or None if it couldn't be read.
+fn file_hash(path: &PathBuf) -> Option<String> {
+    match fs::read(path) {
+        Ok(bytes) => Some(format!("{:x}", Sha256::digest(&bytes))),
+        Err(e) => {
+            warn!("Failed to hash {:?}: {}", path, e);
+            None
+        },
+    }
+}
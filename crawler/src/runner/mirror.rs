@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::Config;
+use super::disk::disk_usage;
+use super::git::{git_timeout, TIMED_OUT};
+
+use log::{debug, error, info, warn};
+
+/// File touched each time a mirror is used, for LRU eviction.
+static LAST_USED: &str = "crawler-last-used";
+
+/// Size & last use of a mirror in the cache.
+struct Entry {
+    used: SystemTime,
+    size: u64,          // In bytes
+}
+
+/// Directory of bare mirrors, which clones are made from instead of the
+/// network.
+///
+/// Local clones hard-link the mirror's objects, so cloning from a mirror is
+/// cheap, even with full history.
+pub struct Mirror {
+    dir: PathBuf,
+    max_bytes: Option<u64>,
+    timeout: Option<u64>,               // In seconds
+    mirrors: Mutex<HashMap<i64, Entry>>,
+    in_use: Mutex<HashSet<i64>>,        // Mirrors that can't be evicted
+}
+
+impl Mirror {
+    /// Create the mirror cache, or return None if it isn't configured.
+    pub fn new(config: &Config) -> Option<Self> {
+        let dir = config.runner.mirror_dir.clone()?;
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("Failed to create mirror directory {:?}: {}", dir, e);
+            return None;
+        }
+
+        let max_bytes = config.runner.mirror_max_size.map(|mb| mb * 1024 * 1024);
+        let mirrors = Mutex::new(scan(&dir));
+        return Some(Self {
+            dir,
+            max_bytes,
+            timeout: config.runner.clone_timeout,
+            mirrors,
+            in_use: Default::default(),
+        });
+    }
+
+    /// Return the path of the mirror of repo ID.
+    fn path(&self, id: i64) -> PathBuf {
+        return self.dir.join(format!("{}.git", id));
+    }
+
+    /// Create or update the mirror of repo ID from URL, & return its path.
+    ///
+    /// The mirror can't be evicted until it is released.
+    pub fn acquire(&self, id: i64, url: &str) -> Result<PathBuf, String> {
+        self.in_use.lock().unwrap().insert(id);
+
+        let path = self.path(id);
+        let result = if path.exists() {
+            debug!("Updating mirror {:?}", path);
+            git_timeout(Some(&path), &["remote", "update", "--prune"], self.timeout)
+        } else {
+            info!("Creating mirror of '{}' at {:?}", url, path);
+            git_timeout(None, &["clone", "--mirror", url, path.to_str().unwrap()], self.timeout)
+        };
+
+        if let Err(e) = result {
+            if e == TIMED_OUT {
+                warn!("Timed out updating mirror {:?}", path);
+            } else {
+                warn!("Failed to update mirror {:?}: {}", path, e);
+            }
+            let _ = fs::remove_dir_all(&path);
+            self.mirrors.lock().unwrap().remove(&id);
+            self.release(id);
+            return Err(e);
+        }
+
+        // Mark the mirror as recently used, & record its new size
+        let _ = fs::write(path.join(LAST_USED), "");
+        let entry = Entry { used: SystemTime::now(), size: disk_usage(&path) };
+        self.mirrors.lock().unwrap().insert(id, entry);
+
+        self.evict();
+        return Ok(path);
+    }
+
+    /// Return the recorded size of the mirror of repo ID, in bytes.
+    pub fn size(&self, id: i64) -> Option<u64> {
+        return self.mirrors.lock().unwrap().get(&id).map(|e| e.size);
+    }
+
+    /// Allow the mirror of repo ID to be evicted.
+    pub fn release(&self, id: i64) {
+        self.in_use.lock().unwrap().remove(&id);
+    }
+
+    /// Remove the least recently used mirrors until the cache fits in the
+    /// size limit, using the recorded size of each mirror.
+    fn evict(&self) {
+        let Some(max) = self.max_bytes else {
+            return;
+        };
+
+        let mut mirrors = self.mirrors.lock().unwrap();
+        let mut total: u64 = mirrors.values().map(|e| e.size).sum();
+        if total <= max {
+            return;
+        }
+
+        // Oldest first
+        let mut order: Vec<(SystemTime, i64)> = mirrors.iter()
+            .map(|(id, e)| (e.used, *id))
+            .collect();
+        order.sort();
+
+        let in_use = self.in_use.lock().unwrap();
+        for (_, id) in order {
+            if total <= max {
+                break;
+            }
+            if in_use.contains(&id) {
+                continue;
+            }
+
+            let path = self.path(id);
+            let size = mirrors[&id].size;
+            info!("Evicting mirror {:?} ({} bytes)", path, size);
+            if fs::remove_dir_all(&path).is_ok() {
+                mirrors.remove(&id);
+                total -= size;
+            }
+        }
+    }
+}
+
+/// Find the size & last use of each mirror already in DIR.
+fn scan(dir: &PathBuf) -> HashMap<i64, Entry> {
+    let mut mirrors = HashMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return mirrors;
+    };
+
+    for path in entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()) {
+        let id = path.file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<i64>().ok());
+        let Some(id) = id else {
+            continue;
+        };
+
+        let used = fs::metadata(path.join(LAST_USED))
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        mirrors.insert(id, Entry { used, size: disk_usage(&path) });
+    }
+
+    return mirrors;
+}
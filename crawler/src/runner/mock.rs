@@ -0,0 +1,139 @@
+// Helpers for testing the runner against a local mock server.
+
+use crate::config::Config;
+use super::db::Database;
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Database used by tests that need one, as `user:password@host/database`.
+/// Those tests are skipped if it isn't set.
+static DATABASE_ENV: &str = "CRAWLER_TEST_DATABASE";
+
+/// A canned HTTP response.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(body: String) -> Self {
+        return Self { status: 200, headers: vec![], body };
+    }
+}
+
+/// HTTP server on localhost that answers each request with a handler, &
+/// records the path of every request it receives.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Start a server that answers requests using HANDLER, which is given the
+    /// path & query of each request.
+    pub fn start(handler: impl Fn(&str) -> Response + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Some(path) = respond(stream, &handler) {
+                    recorded.lock().unwrap().push(path);
+                }
+            }
+        });
+
+        return Self { url, requests };
+    }
+
+    /// Return the paths requested so far.
+    pub fn requests(&self) -> Vec<String> {
+        return self.requests.lock().unwrap().clone();
+    }
+}
+
+/// Answer a single request on STREAM using HANDLER, & return its path.
+fn respond(stream: TcpStream, handler: &impl Fn(&str) -> Response) -> Option<String> {
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request).ok()?;
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+        line.clear();
+    }
+
+    let path = request.split_whitespace().nth(1)?.to_string();
+    let response = handler(&path);
+
+    let mut out = format!("HTTP/1.1 {} Mock\r\n", response.status);
+    for (name, value) in &response.headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str(&format!(
+        "Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.body.len(), response.body
+    ));
+
+    let mut stream = &stream;
+    stream.write_all(out.as_bytes()).ok()?;
+    return Some(path);
+}
+
+/// Return a config for tests, with EXTRA appended to it. The database is
+/// taken from `CRAWLER_TEST_DATABASE`, if set.
+pub fn config(extra: &str) -> Config {
+    let (user, password, host, database) = env::var(DATABASE_ENV).ok()
+        .and_then(|d| parse_database(&d))
+        .unwrap_or_default();
+
+    let toml = format!(
+        r#"
+        [miner]
+        threads = 1
+        tries = 1
+
+        [runner]
+        threads = 1
+        min_stars = 10
+        languages = ["c"]
+        log_dir = "/tmp"
+        log_level = "off"
+        tmp_dir = "/tmp"
+
+        [database]
+        user = "{}"
+        password = "{}"
+        host = "{}"
+        database = "{}"
+
+        {}
+        "#,
+        user, password, host, database, extra
+    );
+
+    return toml::from_str(&toml).unwrap();
+}
+
+/// Connect to the test database, or return None if there isn't one.
+pub fn database(config: &Config) -> Option<Database> {
+    if env::var(DATABASE_ENV).is_err() {
+        eprintln!("Skipped, {} isn't set", DATABASE_ENV);
+        return None;
+    }
+    return Some(Database::new(config));
+}
+
+/// Split `user:password@host/database`.
+fn parse_database(url: &str) -> Option<(String, String, String, String)> {
+    let (login, location) = url.rsplit_once('@')?;
+    let (user, password) = login.split_once(':').unwrap_or((login, ""));
+    let (host, database) = location.split_once('/')?;
+    return Some((user.into(), password.into(), host.into(), database.into()));
+}
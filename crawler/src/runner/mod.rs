@@ -1,22 +1,83 @@
 pub mod db;
+mod control;
+mod disk;
 mod git;
+mod github;
+mod history;
+mod lease;
+mod manifest;
+mod mirror;
+#[cfg(test)]
+mod mock;
+mod partition;
+mod prefetch;
+mod progress;
+mod report;
 mod search;
+mod source;
+mod status;
+mod submodule;
+mod tokens;
 mod run;
+mod watchdog;
 
 use crate::config::Config;
 use crate::interface::AnyInterface;
-use search::Search;
+use crate::miner;
+use git::{is_archive, RepoData};
+use manifest::Manifest;
+pub use report::report_failures;
+pub use search::SearchMode;
 use run::run_all;
 
+use std::path::PathBuf;
+use log::error;
+
 pub fn crawl(config: &Config, interface: AnyInterface) {
     // Search for matching repositories
-    search(config);
+    search(config, SearchMode::Resume);
 
     // Mine each repository
     run_all(config, interface);
 }
 
-pub fn search(config: &Config) {
+/// Add the repositories from each configured source to the database.
+pub fn search(config: &Config, mode: SearchMode) {
     let db = db::Database::new(config);
-    let _search = Search::new(config, &db);
+    source::collect_all(config, &db, mode);
+}
+
+/// Mine a single repository or source archive on disk.
+pub fn mine_one(path: PathBuf, config: Config, interface: AnyInterface) {
+    // Record the versions & config the repository was mined with
+    let db = db::Database::new(&config);
+    let manifest = Manifest::start("mine", &config, &db, &interface);
+
+    mine_path(path, config, interface);
+    manifest.finish(&db);
+}
+
+fn mine_path(path: PathBuf, config: Config, interface: AnyInterface) {
+    let url = path.to_string_lossy().to_string();
+    if !is_archive(&url) {
+        miner::mine_one(path, config, interface);
+        return;
+    }
+
+    // Extract the archive, it is deleted once the repo is dropped
+    let id = match source::content_id(&path) {
+        Ok(id) => id,
+        Err(e) => {
+            error!("Failed to read {:?}: {}", path, e);
+            return;
+        },
+    };
+    let mut repo = RepoData::new(id, &url, &url, 0);
+    if repo.extract(&config.runner.tmp_dir).is_err() {
+        error!("Failed to extract {:?}", path);
+        return;
+    }
+    if let Some(dir) = repo.dir.clone() {
+        miner::mine_one(dir, config, interface);
+    }
 }
@@ -0,0 +1,197 @@
+use chrono::{Days, Local, NaiveDate};
+
+/// Date of the oldest possible repository.
+static GITHUB_EPOCH: &str = "2008-01-01";
+static DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A slice of the search space.
+///
+/// GitHub only returns the first 1000 results of any query, so a query that
+/// matches more repositories than this must be split into smaller windows. A
+/// window is first split by star count, and once it only covers a single star
+/// count, by creation date.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Window {
+    pub min_stars: usize,
+    pub max_stars: usize,
+    pub created_from: NaiveDate,    // Inclusive
+    pub created_to: NaiveDate,      // Inclusive
+}
+
+impl Window {
+    /// Create the window covering all repositories with at least MIN_STARS.
+    ///
+    /// CREATED is the `created:` qualifier from the config, if any.
+    pub fn new(min_stars: usize, max_stars: usize, created: Option<&str>) -> Self {
+        let (created_from, created_to) = match created {
+            Some(range) => parse_range(range),
+            None => (epoch(), today()),
+        };
+
+        return Self { min_stars, max_stars, created_from, created_to };
+    }
+
+    /// Split the window in two, or return None if it can't be split further.
+    pub fn split(&self) -> Option<(Window, Window)> {
+        // Split by stars first
+        if self.min_stars < self.max_stars {
+            let mid = self.min_stars + (self.max_stars - self.min_stars) / 2;
+            let low = Window { max_stars: mid, ..self.clone() };
+            let high = Window { min_stars: mid + 1, ..self.clone() };
+            return Some((low, high));
+        }
+
+        // Otherwise, split by creation date
+        if self.created_from < self.created_to {
+            let days = (self.created_to - self.created_from).num_days() as u64;
+            let mid = self.created_from + Days::new(days / 2);
+            let early = Window { created_to: mid, ..self.clone() };
+            let late = Window { created_from: mid + Days::new(1), ..self.clone() };
+            return Some((early, late));
+        }
+
+        return None;
+    }
+
+    /// Return the part of the window created after DATE, or None if there
+    /// isn't one.
+    pub fn created_after(&self, date: NaiveDate) -> Option<Window> {
+        if date >= self.created_to {
+            return None;
+        }
+
+        let created_from = self.created_from.max(date + Days::new(1));
+        return Some(Window { created_from, ..self.clone() });
+    }
+
+    /// Return the qualifiers selecting this window.
+    pub fn qualifiers(&self) -> Vec<String> {
+        vec![
+            format!("stars:{}..{}", self.min_stars, self.max_stars),
+            format!(
+                "created:{}..{}",
+                self.created_from.format(DATE_FORMAT),
+                self.created_to.format(DATE_FORMAT),
+            ),
+        ]
+    }
+}
+
+/// Result of searching a single window.
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub window: Window,
+    pub total: usize,       // Number of results reported by GitHub
+    pub found: usize,       // Number of new repositories added
+    pub complete: bool,     // False if the window was saturated
+}
+
+fn epoch() -> NaiveDate {
+    return parse_date(GITHUB_EPOCH);
+}
+
+fn today() -> NaiveDate {
+    return Local::now().date_naive();
+}
+
+fn parse_date(date: &str) -> NaiveDate {
+    match NaiveDate::parse_from_str(date, DATE_FORMAT) {
+        Ok(d) => d,
+        Err(e) => panic!("Invalid date '{}': {}", date, e),
+    }
+}
+
+/// Parse a `created:` range into inclusive start & end dates.
+///
+/// Supports `A..B`, `>A`, `>=A`, `<B`, and `<=B`.
+fn parse_range(range: &str) -> (NaiveDate, NaiveDate) {
+    if let Some((from, to)) = range.split_once("..") {
+        return (parse_date(from), parse_date(to));
+    }
+    if let Some(from) = range.strip_prefix(">=") {
+        return (parse_date(from), today());
+    }
+    if let Some(from) = range.strip_prefix('>') {
+        return (parse_date(from) + Days::new(1), today());
+    }
+    if let Some(to) = range.strip_prefix("<=") {
+        return (epoch(), parse_date(to));
+    }
+    if let Some(to) = range.strip_prefix('<') {
+        return (epoch(), parse_date(to) - Days::new(1));
+    }
+    panic!("Invalid created range: '{}'", range);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(min_stars: usize, max_stars: usize, from: &str, to: &str) -> Window {
+        return Window {
+            min_stars,
+            max_stars,
+            created_from: parse_date(from),
+            created_to: parse_date(to),
+        };
+    }
+
+    #[test]
+    fn split_by_stars_first() {
+        let (low, high) = window(10, 21, "2020-01-01", "2020-12-31").split().unwrap();
+        assert_eq!(low, window(10, 15, "2020-01-01", "2020-12-31"));
+        assert_eq!(high, window(16, 21, "2020-01-01", "2020-12-31"));
+    }
+
+    #[test]
+    fn split_by_date_at_single_star_count() {
+        let (early, late) = window(10, 10, "2020-01-01", "2020-01-10").split().unwrap();
+        assert_eq!(early, window(10, 10, "2020-01-01", "2020-01-05"));
+        assert_eq!(late, window(10, 10, "2020-01-06", "2020-01-10"));
+    }
+
+    #[test]
+    fn split_covers_every_day() {
+        let (early, late) = window(10, 10, "2020-01-01", "2020-01-02").split().unwrap();
+        assert_eq!(early, window(10, 10, "2020-01-01", "2020-01-01"));
+        assert_eq!(late, window(10, 10, "2020-01-02", "2020-01-02"));
+    }
+
+    #[test]
+    fn no_split_of_single_day_and_star_count() {
+        assert_eq!(window(10, 10, "2020-01-01", "2020-01-01").split(), None);
+    }
+
+    #[test]
+    fn parse_created_ranges() {
+        let d = parse_date;
+        assert_eq!(parse_range("2015-01-01..2020-01-01"), (d("2015-01-01"), d("2020-01-01")));
+        assert_eq!(parse_range(">=2015-01-01"), (d("2015-01-01"), today()));
+        assert_eq!(parse_range(">2015-01-01"), (d("2015-01-02"), today()));
+        assert_eq!(parse_range("<=2015-01-01"), (epoch(), d("2015-01-01")));
+        assert_eq!(parse_range("<2015-01-01"), (epoch(), d("2014-12-31")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid date")]
+    fn parse_invalid_date() {
+        parse_range("2015-13-01..2020-01-01");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid created range")]
+    fn parse_invalid_range() {
+        parse_range("2015-01-01");
+    }
+
+    #[test]
+    fn created_after_last_search() {
+        let root = window(10, 100, "2020-01-01", "2020-12-31");
+        assert_eq!(
+            root.created_after(parse_date("2020-06-30")),
+            Some(window(10, 100, "2020-07-01", "2020-12-31")),
+        );
+        assert_eq!(root.created_after(parse_date("2020-12-31")), None);
+        assert_eq!(root.created_after(parse_date("2019-01-01")), Some(root.clone()));
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::{Condvar, Mutex};
+
+use log::debug;
+
+/// Disk space used by repositories that have been cloned ahead of the miners,
+/// so prefetching can't fill `tmp_dir`.
+pub struct Budget {
+    max: Option<u64>,   // In bytes
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl Budget {
+    /// Create a budget of MAX_MB megabytes, or an unlimited budget if None.
+    pub fn new(max_mb: Option<u64>) -> Self {
+        return Self {
+            max: max_mb.map(|mb| mb * 1024 * 1024),
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        };
+    }
+
+    /// Block until the budget has space for another repository.
+    pub fn wait(&self) {
+        let Some(max) = self.max else {
+            return;
+        };
+
+        let mut used = self.used.lock().unwrap();
+        while *used >= max {
+            debug!("Prefetch budget full, waiting for a repository to be mined");
+            used = self.freed.wait(used).unwrap();
+        }
+    }
+
+    /// Add a repository of BYTES to the budget.
+    pub fn add(&self, bytes: u64) {
+        *self.used.lock().unwrap() += bytes;
+    }
+
+    /// Remove a repository of BYTES from the budget, once it has been mined.
+    pub fn free(&self, bytes: u64) {
+        let mut used = self.used.lock().unwrap();
+        *used = used.saturating_sub(bytes);
+        self.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Assert that waiting on BUDGET blocks until FREED bytes are freed.
+    fn assert_blocks_until_freed(budget: &Budget, freed: u64) {
+        thread::scope(|s| {
+            let (tx, rx) = mpsc::channel();
+            s.spawn(move || {
+                budget.wait();
+                tx.send(()).unwrap();
+            });
+
+            assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+            budget.free(freed);
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        });
+    }
+
+    #[test]
+    fn wait_until_freed() {
+        let budget = Budget::new(Some(2));
+        budget.wait();
+        budget.add(MB);
+        budget.wait();
+        budget.add(MB);
+
+        assert_blocks_until_freed(&budget, MB);
+        assert_eq!(*budget.used.lock().unwrap(), MB);
+    }
+
+    #[test]
+    fn oversized_repo_gets_through() {
+        let budget = Budget::new(Some(1));
+        budget.wait();
+        budget.add(10 * MB);
+
+        assert_blocks_until_freed(&budget, 10 * MB);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn unlimited_never_waits() {
+        let budget = Budget::new(None);
+        budget.add(u64::MAX);
+        budget.wait();
+    }
+}
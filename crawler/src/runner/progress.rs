@@ -0,0 +1,231 @@
+use super::db::Database;
+use super::partition::Window;
+
+use chrono::NaiveDate;
+use sqlx::{self, any::AnyRow, Any, Row};
+
+static DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// State of a window in the `search_progress` table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WindowState {
+    Pending,        // Not yet fully searched
+    Split,          // Replaced by two smaller windows
+    Done,           // All pages were added
+    Incomplete,     // Saturated, but couldn't be split
+}
+
+impl WindowState {
+    fn name(&self) -> &'static str {
+        match self {
+            WindowState::Pending    => "pending",
+            WindowState::Split      => "split",
+            WindowState::Done       => "done",
+            WindowState::Incomplete => "incomplete",
+        }
+    }
+}
+
+/// A window, along with how far through it we are.
+#[derive(Clone, Debug)]
+pub struct Cursor {
+    pub window: Window,
+    pub pages: usize,       // Number of pages already added
+    pub total: usize,       // Number of results, if known
+}
+
+impl Cursor {
+    /// Create a cursor at the start of WINDOW.
+    pub fn new(window: Window) -> Self {
+        return Self { window, pages: 0, total: 0 };
+    }
+
+    /// Create a cursor from a row of the `search_progress` table.
+    fn from_row(row: AnyRow) -> Result<Self, sqlx::Error> {
+        let min   = row.try_get::<i64, usize>(0)?;
+        let max   = row.try_get::<i64, usize>(1)?;
+        let from  = row.try_get::<&[u8], usize>(2)?;
+        let to    = row.try_get::<&[u8], usize>(3)?;
+        let pages = row.try_get::<i64, usize>(4)?;
+        let total = row.try_get::<i64, usize>(5)?;
+
+        let date = |s: &[u8]| {
+            let s = String::from_utf8_lossy(s);
+            NaiveDate::parse_from_str(&s, DATE_FORMAT)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+
+        let window = Window {
+            min_stars: min as usize,
+            max_stars: max as usize,
+            created_from: date(from)?,
+            created_to: date(to)?,
+        };
+
+        return Ok(Self { window, pages: pages as usize, total: total as usize });
+    }
+}
+
+/// Persisted record of which search windows & pages have been completed.
+pub struct Progress<'a> {
+    db: &'a Database,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        return Self { db };
+    }
+
+    /// Remove all progress.
+    pub fn clear(&self) -> Result<(), sqlx::Error> {
+        self.db.rt.block_on(async {
+            sqlx::query::<Any>("delete from search_progress")
+                .execute(&self.db.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Return true if no windows have been recorded.
+    pub fn is_empty(&self) -> Result<bool, sqlx::Error> {
+        self.db.rt.block_on(async {
+            let row = sqlx::query::<Any>("select count(*) from search_progress")
+                .fetch_one(&self.db.pool)
+                .await?;
+            Ok(row.try_get::<i64, usize>(0)? == 0)
+        })
+    }
+
+    /// Return all pending windows, with the highest star counts last.
+    pub fn pending(&self) -> Result<Vec<Cursor>, sqlx::Error> {
+        self.db.rt.block_on(async {
+            let rows = sqlx::query::<Any>(
+                "select min_stars, max_stars, created_from, created_to, pages, total
+                 from search_progress
+                 where state = ?
+                 order by max_stars, created_to"
+            ).bind(WindowState::Pending.name())
+             .fetch_all(&self.db.pool)
+             .await?;
+
+            let mut acc = vec![];
+            for row in rows {
+                acc.push(Cursor::from_row(row)?);
+            }
+            Ok(acc)
+        })
+    }
+
+    /// Return the latest creation date covered by any window, or None if no
+    /// windows have been recorded.
+    pub fn last_created(&self) -> Result<Option<NaiveDate>, sqlx::Error> {
+        self.db.rt.block_on(async {
+            let row = sqlx::query::<Any>("select max(created_to) from search_progress")
+                .fetch_one(&self.db.pool)
+                .await?;
+
+            let Some(date) = row.try_get::<Option<&[u8]>, usize>(0)? else {
+                return Ok(None);
+            };
+            let date = NaiveDate::parse_from_str(&String::from_utf8_lossy(date), DATE_FORMAT)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            Ok(Some(date))
+        })
+    }
+
+    /// Mark all finished windows as pending, so they are searched again.
+    pub fn reset_finished(&self) -> Result<(), sqlx::Error> {
+        self.db.rt.block_on(async {
+            sqlx::query::<Any>(
+                "update search_progress
+                 set state = ?, pages = 0
+                 where state = ? or state = ?"
+            ).bind(WindowState::Pending.name())
+             .bind(WindowState::Done.name())
+             .bind(WindowState::Incomplete.name())
+             .execute(&self.db.pool)
+             .await?;
+            Ok(())
+        })
+    }
+
+    /// Record a new pending window.
+    pub fn add(&self, window: &Window) -> Result<(), sqlx::Error> {
+        self.db.rt.block_on(async {
+            let mut conn = self.db.pool.acquire().await?;
+            Self::insert(&mut conn, window).await
+        })
+    }
+
+    /// Replace WINDOW with its two halves.
+    pub fn split(&self, window: &Window, low: &Window, high: &Window) -> Result<(), sqlx::Error> {
+        self.db.rt.block_on(async {
+            let mut tx = self.db.pool.begin().await?;
+            Self::insert(tx.as_mut(), low).await?;
+            Self::insert(tx.as_mut(), high).await?;
+            Self::update(tx.as_mut(), window, WindowState::Split, 0, 0).await?;
+            tx.commit().await?;
+            Ok(())
+        })
+    }
+
+    /// Record that PAGES pages of WINDOW have been added.
+    pub fn set_pages(&self, window: &Window, pages: usize, total: usize) -> Result<(), sqlx::Error> {
+        self.db.rt.block_on(async {
+            let mut conn = self.db.pool.acquire().await?;
+            Self::update(&mut conn, window, WindowState::Pending, pages, total).await
+        })
+    }
+
+    /// Record that WINDOW has been finished.
+    pub fn finish(&self, window: &Window, complete: bool, pages: usize, total: usize) -> Result<(), sqlx::Error> {
+        let state = match complete {
+            true => WindowState::Done,
+            false => WindowState::Incomplete,
+        };
+
+        self.db.rt.block_on(async {
+            let mut conn = self.db.pool.acquire().await?;
+            Self::update(&mut conn, window, state, pages, total).await
+        })
+    }
+
+    async fn insert(conn: &mut sqlx::AnyConnection, window: &Window) -> Result<(), sqlx::Error> {
+        sqlx::query::<Any>(
+            "insert ignore into search_progress values (?, ?, ?, ?, ?, 0, 0)"
+        ).bind(window.min_stars as i64)
+         .bind(window.max_stars as i64)
+         .bind(window.created_from.format(DATE_FORMAT).to_string())
+         .bind(window.created_to.format(DATE_FORMAT).to_string())
+         .bind(WindowState::Pending.name())
+         .execute(conn)
+         .await?;
+
+        Ok(())
+    }
+
+    async fn update(
+        conn: &mut sqlx::AnyConnection,
+        window: &Window,
+        state: WindowState,
+        pages: usize,
+        total: usize,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query::<Any>(
+            "update search_progress
+             set state = ?, pages = ?, total = ?
+             where min_stars = ? and max_stars = ?
+             and created_from = ? and created_to = ?"
+        ).bind(state.name())
+         .bind(pages as i64)
+         .bind(total as i64)
+         .bind(window.min_stars as i64)
+         .bind(window.max_stars as i64)
+         .bind(window.created_from.format(DATE_FORMAT).to_string())
+         .bind(window.created_to.format(DATE_FORMAT).to_string())
+         .execute(conn)
+         .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::Config;
+use crate::miner::Failure;
+use super::db::Database;
+
+use log::error;
+use sqlx::{self, Any, Row};
+
+/// Print why attempts failed over the whole corpus, along with the LIMIT
+/// headers & identifiers that were missing from the most files.
+pub fn report_failures(config: &Config, limit: usize) {
+    let db = Database::new(config);
+    if let Err(e) = db.rt.block_on(print_failures(&db, limit)) {
+        error!("Failed to report failures: {:?}", e);
+    }
+}
+
+async fn print_failures(db: &Database, limit: usize) -> Result<(), sqlx::Error> {
+    // Failed attempts of each category
+    let rows = sqlx::query::<Any>(
+        "select category, cast(sum(n) as signed) as total
+         from failures
+         group by category
+         order by total desc"
+    ).fetch_all(&db.pool)
+        .await?;
+
+    let mut counts = vec![];
+    for row in rows {
+        let category = row.try_get::<&[u8], usize>(0)?;
+        let n = row.try_get::<i64, usize>(1)?;
+        counts.push((String::from_utf8_lossy(category).to_string(), n));
+    }
+    let total: i64 = counts.iter().map(|c| c.1).sum();
+
+    println!("Failed attempts: {}", total);
+    for (category, n) in &counts {
+        let percent = 100.0 * *n as f64 / total.max(1) as f64;
+        println!("  {:<24} {:>10}  {:>5.1}%", category, n, percent);
+    }
+
+    let sections = [
+        ("Missing user headers", Failure::MissingUserHeader),
+        ("Missing system headers", Failure::MissingSystemHeader),
+        ("Undeclared identifiers", Failure::UndeclaredIdentifier),
+        ("Unknown types", Failure::UnknownType),
+    ];
+    for (title, failure) in sections {
+        println!();
+        println!("{} (files, repos):", title);
+        for (subject, files, repos) in top_subjects(db, failure, limit).await? {
+            println!("  {:<40} {:>8} {:>6}", subject, files, repos);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Return the LIMIT subjects of FAILURE missing from the most files, with
+/// the number of files & repos that were missing each.
+async fn top_subjects(db: &Database, failure: Failure, limit: usize) -> Result<Vec<(String, i64, i64)>, sqlx::Error> {
+    let rows = sqlx::query::<Any>(
+        "select subject, cast(sum(n) as signed) as files, count(distinct repo_id)
+         from failure_subjects
+         where category = ?
+         group by subject
+         order by files desc
+         limit ?"
+    ).bind(failure.name())
+        .bind(limit as i64)
+        .fetch_all(&db.pool)
+        .await?;
+
+    let mut subjects = vec![];
+    for row in rows {
+        let subject = row.try_get::<&[u8], usize>(0)?;
+        let files = row.try_get::<i64, usize>(1)?;
+        let repos = row.try_get::<i64, usize>(2)?;
+        subjects.push((String::from_utf8_lossy(subject).to_string(), files, repos));
+    }
+
+    return Ok(subjects);
+}
@@ -1,17 +1,29 @@
-use crate::config::Config;
+use crate::config::{Config, History};
 use crate::interface::{AnyInterface, InitInput, InternInput};
-use crate::miner::{mine, MineResult};
+use crate::metrics;
+use crate::miner::{mine_cached, FileProgress, MineCache, MineResult};
+use super::control::Control;
 use super::db;
-use super::git::RepoData;
+use super::disk;
+use super::git::{FetchError, RepoData, TIMED_OUT};
+use super::history::{self, Revision};
+use super::lease::Leases;
+use super::manifest::Manifest;
+use super::mirror::Mirror;
+use super::prefetch::Budget;
+use super::status::Status;
+use super::submodule;
+use super::watchdog::Watchdog;
 
-use rayon::iter::IntoParallelRefIterator;
-use rayon::{current_thread_index, prelude::*, ThreadPool};
-use sqlx::{self, Any};
-use log::{info, error};
-use crossbeam::sync::WaitGroup;
+use rayon::ThreadPool;
+use sqlx::{self, Any, Row};
+use log::{info, error, warn};
+use std::collections::HashSet;
 use std::panic::AssertUnwindSafe;
-use std::sync::mpsc;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // =============================================================================
 // Top-Level Runner
@@ -20,6 +32,9 @@ use std::time::Instant;
 pub fn run_all(config: &Config, interface: AnyInterface) {
     let db = db::Database::new(config);
 
+    // Record the versions & config this crawl was run with
+    let manifest = Manifest::start("crawl", config, &db, &interface);
+
     // Call the user supplied init function
     info!("Initializing instance");
     let input = InitInput { config, db: &db };
@@ -28,9 +43,25 @@ pub fn run_all(config: &Config, interface: AnyInterface) {
         Err(e) => { panic!("Failed to initialize instance: {:?}", e) }
     }
 
-    // Get all un-mined repos
-    let repos = db.rt.block_on(un_mined_repos(&db))
-                     .expect("Failed to fetch repos");
+    // Claim repositories so other workers don't mine them too
+    let leases = Leases::new(config, &db);
+    info!("Mining as worker '{}'", leases.worker());
+
+    // Remove repositories left behind by a crash, unless another worker is
+    // still mining them
+    disk::clean_orphans(&config.runner.tmp_dir, &leases.claimed());
+
+    // Stop or pause on signals
+    let control = Control::install(config);
+
+    // Create the mirror cache, if enabled
+    let mirror = Mirror::new(config);
+
+    // Log repos that stop making progress
+    let watchdog = Watchdog::new(config);
+
+    // Show the progress of the crawl
+    let status = Status::new(config, &db);
 
     // Create the runner thread pool
     info!("Creating runner thread pool");
@@ -40,144 +71,642 @@ pub fn run_all(config: &Config, interface: AnyInterface) {
         .build()
         .expect("Failed to create runner thread pool");
 
-    // Create miner thread pools
-    info!("Creating miner thread pools");
-    let miner_pools: Vec<_> = (0..config.runner.threads)
-        .map(|i| {
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(config.miner.threads)
-                .thread_name(move |ii| format!("mine-{}:{}", i, ii))
-                .build()
-                .expect("Failed to create miner thread pool")
-        })
-        .collect();
-
-    // Mine all repos
-    info!("Mining {} repositories", repos.len());
-    run_pool.install(|| {
-        let _ = repos.par_iter().for_each(|repo| {
-            // Mine a single repo
-            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
-                let pool = &miner_pools[current_thread_index().unwrap()];
-                let mut runner = Runner::new(config, pool, &db, repo.clone(), interface.clone());
-                runner.run();
-            }));
-
-            // Error out if there is a panic
-            if let Err(_) = result {
-                error!("Runner paniced")
-            }
-        });
-    })
-}
+    // Create a single compile pool shared by every runner. Each runner adds
+    // the files of its repository to the pool's queue, & idle threads steal
+    // files from other repositories, so small repositories don't leave
+    // threads idle while a large one is mined.
+    let compile_threads = config.runner.compile_threads
+        .unwrap_or(config.runner.threads * config.miner.threads);
+    info!("Creating compile thread pool with {} threads", compile_threads);
+    let compile_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(compile_threads)
+        .thread_name(|i| format!("mine-{}", i))
+        .build()
+        .expect("Failed to create compile thread pool");
 
-async fn un_mined_repos(db: &db::Database) -> Result<Vec<RepoData>, sqlx::Error> {
-    // Fetch the results
-    let rows = sqlx::query(
-        "select *
-         from repos
-         where repo_id not in (select repo_id from mined)
-        ").fetch_all(&db.pool).await?;
+    // Create the clone thread pool, if prefetching
+    let prefetch = config.runner.prefetch;
+    let clone_pool = (prefetch > 0).then(|| {
+        info!("Creating clone thread pool");
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(config.runner.clone_threads)
+            .thread_name(|i| format!("clone-{}", i))
+            .build()
+            .expect("Failed to create clone thread pool")
+    });
 
-    let mut acc = vec![];
-    for row in rows {
-        let row = RepoData::from_row(row)?;
-        acc.push(row);
+    // Claim, clone, & mine repos until there are none left to claim
+    let crawl = Crawl {
+        config,
+        pool: &compile_pool,
+        db: &db,
+        run_id: manifest.id,
+        control: &control,
+        mirror: mirror.as_ref(),
+        watchdog: &watchdog,
+        status: &status,
+    };
+    let new_runner = |repo| Runner::new(&crawl, repo, interface.clone());
+    let budget = Budget::new(config.runner.prefetch_max_size);
+    let (stop_renewing, renew_rx) = mpsc::channel::<()>();
+    let (stop_watching, watch_rx) = mpsc::channel::<()>();
+    let (stop_status, status_rx) = mpsc::channel::<()>();
+    thread::scope(|s| {
+        s.spawn(|| leases.renew_until(renew_rx));
+        s.spawn(|| watchdog.run_until(watch_rx));
+        s.spawn(|| status.run_until(status_rx));
+
+        match &clone_pool {
+            None => {
+                // Clone & mine each repo on the same thread
+                run_pool.broadcast(|_| {
+                    while let Some(mut runner) = next_runner(&leases, &control, &new_runner) {
+                        let id = runner.repo.id;
+                        catch_panic(|| {
+                            if runner.fetch() {
+                                runner.mine();
+                            }
+                        });
+                        leases.release(id);
+                    }
+                });
+            },
+            Some(clone_pool) => {
+                // Clone repos on the clone pool, keeping up to PREFETCH
+                // cloned repos waiting to be mined
+                let (tx, rx) = mpsc::sync_channel::<Runner>(prefetch);
+                let rx = Mutex::new(rx);
+                s.spawn(|| {
+                    let tx = tx;
+                    clone_pool.broadcast(|_| {
+                        loop {
+                            // Wait for space in the disk budget before cloning more
+                            budget.wait();
+                            let Some(mut runner) = next_runner(&leases, &control, &new_runner) else {
+                                break;
+                            };
+
+                            let id = runner.repo.id;
+                            let fetched = catch_panic(|| runner.fetch());
+                            if fetched != Some(true) {
+                                leases.release(id);
+                                continue;
+                            }
+
+                            budget.add(runner.peak_disk);
+                            if tx.send(runner).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                });
+
+                // Mine the cloned repos
+                run_pool.broadcast(|_| {
+                    loop {
+                        let next = rx.lock().unwrap().recv();
+                        let Ok(mut runner) = next else {
+                            break;
+                        };
+
+                        let id = runner.repo.id;
+                        let size = runner.peak_disk;
+                        catch_panic(|| runner.mine());
+                        drop(runner);
+
+                        budget.free(size);
+                        leases.release(id);
+                    }
+                });
+            },
+        }
+
+        drop(stop_renewing);
+        drop(stop_watching);
+        drop(stop_status);
+    });
+
+    if control.stopping() {
+        info!("Stopped, the remaining repositories will be mined by the next crawl");
+    }
+    manifest.finish(&db);
+    log::logger().flush();
+}
+
+/// Claim the next repo & create its runner using NEW_RUNNER. Returns None once
+/// there are no repos left, or the crawl is stopping.
+fn next_runner<'a, F>(leases: &Leases, control: &Control, new_runner: &F) -> Option<Runner<'a>>
+where
+    F: Fn(RepoData) -> Runner<'a>,
+{
+    // Don't start new repos once stopping
+    if !control.proceed() {
+        return None;
     }
 
-    return Ok(acc);
+    let repo = leases.claim_next()?;
+    return Some(new_runner(repo));
+}
+
+/// Run F, returning None if it panics.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Option<T> {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => Some(r),
+        Err(_) => {
+            error!("Runner paniced");
+            metrics::REPOS_FAILED.inc();
+            None
+        },
+    }
 }
 
 // =============================================================================
 // Single Runner
 // =============================================================================
 
-struct Runner<'a> {
+/// State shared by every runner of a crawl.
+struct Crawl<'a> {
     config: &'a Config,
-    pool: &'a ThreadPool,
+    pool: &'a ThreadPool,       // Compile pool
     db: &'a db::Database,
+    run_id: i64,
+    control: &'a Control,
+    mirror: Option<&'a Mirror>,
+    watchdog: &'a Watchdog,
+    status: &'a Status,
+}
+
+struct Runner<'a> {
+    crawl: &'a Crawl<'a>,
     repo: RepoData,
+    progress: Arc<FileProgress>,
     start: Instant,
+    fetch_time: Duration,
+    peak_disk: u64,     // Largest size of the repository on disk, in bytes
     interface: AnyInterface,
 }
 
 impl<'a> Runner<'a> {
     /// Create a new runner
-    pub fn new(config: &'a Config, pool: &'a ThreadPool, db: &'a db::Database, repo: RepoData, interface: AnyInterface) -> Self {
-        return Self { config, pool, db, repo, start: Instant::now(), interface };
-    }
-
-    /// Mine this repo
-    pub fn run(&mut self) {
-        // Clone the repository
-        let _ = self.repo.git_clone(&self.config.runner.tmp_dir);
-
-        // If we don't have the WaitGroup, the current thread continues on and
-        // deletes the repo before we have mined it.
-        let wg = WaitGroup::new();
-
-        let (tx, rx) = mpsc::channel::<MineResult>();
-
-        // Run the miner using our thread pool
-        if let Some(dir) = (&self.repo.dir).clone() {
-            // Get the name of the log file
-            let log_file = self.repo.name.replace("/", "-");
-            let log_file = format!("{}-{}.log", self.repo.id, log_file);
-            let log_path = self.config.runner.log_dir.join(log_file);
-
-            let config = self.config.clone();
-            let wg = wg.clone();
-            let interface = self.interface.clone();
-            self.pool.spawn(move || {
-                // Run the miner
-                if let Ok(result) = mine(&dir, &log_path, config, interface) {
-                    match tx.send(result) {
-                        Ok(_) => {},
-                        Err(e) => {
-                            error!("Failed to send match data: {}", e);
-                        }
-                    }
-                }
-                drop(wg);
-            });
+    pub fn new(crawl: &'a Crawl<'a>, repo: RepoData, interface: AnyInterface) -> Self {
+        return Self {
+            crawl, repo,
+            progress: Default::default(),
+            start: Instant::now(), fetch_time: Duration::ZERO, peak_disk: 0, interface
+        };
+    }
+
+    /// Return the history to mine, or None if only the latest revision is
+    /// mined.
+    fn history(&self) -> Option<&'a History> {
+        // Pinned revisions are only mined once
+        match &self.repo.revision {
+            Some(_) => None,
+            None => self.crawl.config.history.as_ref(),
         }
-        wg.wait();
+    }
+
+    /// Clone or extract this repo, & return true if it can be mined.
+    pub fn fetch(&mut self) -> bool {
+        self.crawl.status.begin(&self.repo, self.progress.clone());
 
-        // Add any matches
-        let result = match rx.try_recv() {
-            Ok(r) => r,
+        // Wait for other repositories to finish if the disk is almost full
+        let tmp_dir = &self.crawl.config.runner.tmp_dir;
+        if let Some(min) = self.crawl.config.runner.min_free_space {
+            disk::wait_for_space(tmp_dir, min * 1024 * 1024);
+        }
+
+        // Clone or extract the repository. The size of repositories from some
+        // sources is only known after cloning.
+        let fetched = self.repo.fetch(self.crawl.config, self.history().is_some(), self.crawl.mirror);
+        let Some(dir) = self.repo.dir.clone() else {
+            self.skip_if_limited(fetched);
+            return false;
+        };
+        let measured = fetched.and_then(|_| self.measure_disk(&dir));
+        if !self.skip_if_limited(measured) {
+            return false;
+        }
+
+        self.fetch_time = self.start.elapsed();
+        metrics::REPOS_CLONED.inc();
+        return true;
+    }
+
+    /// Mine this repo, once it has been fetched.
+    pub fn mine(&mut self) {
+        let Some(dir) = self.repo.dir.clone() else {
+            return;
+        };
+        if self.crawl.control.stopping() {
+            return;
+        }
+
+        // Don't count the time spent waiting to be mined
+        self.start = Instant::now().checked_sub(self.fetch_time).unwrap_or(self.start);
+
+        match self.history() {
+            Some(history) => self.run_history(&dir, history),
+            None => {
+                let commit = self.repo.checkout.as_ref().map(|c| c.commit.clone());
+                let Some(submodules) = self.submodules(&dir) else {
+                    return;
+                };
+                let mut cache = self.new_cache();
+                cache.set_submodules(submodules);
+                let Some(result) = self.mine_dir(&dir, &mut cache, None) else {
+                    metrics::REPOS_FAILED.inc();
+                    return;
+                };
+                let _ = self.measure_disk(&dir);
+                self.intern(&result, commit.as_deref());
+                self.crawl.db.rt.block_on(self.mark_usage(&result, commit.as_deref()));
+                self.crawl.db.rt.block_on(self.mark_failures(&result, commit.as_deref()));
+                self.crawl.db.rt.block_on(self.mark_log(&self.log_path(None), commit.as_deref()));
+                let partial = result.n_skipped > 0;
+                self.crawl.db.rt.block_on(self.mark_as_mined(&result, partial));
+            },
+        }
+
+        info!("Finished mining: '{}'", self.repo.name);
+    }
+
+    /// Mine several revisions of this repo, reusing the miner cache between
+    /// revisions.
+    fn run_history(&mut self, dir: &PathBuf, history: &History) {
+        let revisions = history::revisions(dir, history);
+        info!("Mining {} revisions of '{}'", revisions.len(), self.repo.name);
+
+        // Skip revisions mined by a previous run
+        let done = match self.crawl.db.rt.block_on(self.mined_revisions()) {
+            Ok(d) => d,
             Err(e) => {
-                error!("Failed to receive match data: {:?}", e);
+                error!("Failed to fetch mined revisions: {:?}", e);
+                return;
+            },
+        };
+
+        let mut cache = self.new_cache();
+        let mut previous: Option<String> = None;   // Commit the cache is from
+        let mut total = MineResult {
+            data: vec![], n_files: 0, n_success: 0, n_error: 0, n_skipped: 0,
+            usage: vec![], failures: Default::default(),
+        };
+        let mut partial = false;
+
+        let n_revisions = revisions.len();
+        for (i, rev) in revisions.into_iter().enumerate() {
+            // Leave the remaining revisions for the next crawl
+            if self.crawl.control.stopping() {
+                info!("Stopped mining history of '{}'", self.repo.name);
                 return;
             }
+
+            // Skip the remaining revisions once out of time
+            if self.out_of_time() {
+                warn!(
+                    "Time budget of '{}' ran out, skipping {} revisions",
+                    self.repo.name, n_revisions - i
+                );
+                partial = true;
+                break;
+            }
+
+            if done.contains(&rev.commit) {
+                info!("Already mined revision {} ({})", rev.label, rev.commit);
+                continue;
+            }
+
+            info!("Mining revision {} ({}) of '{}'", rev.label, rev.commit, self.repo.name);
+            // Later revisions would most likely time out too
+            let timeout = self.crawl.config.runner.clone_timeout;
+            match history::checkout(dir, &rev.commit, timeout) {
+                Ok(_) => {},
+                Err(e) if e == TIMED_OUT => {
+                    warn!("Timed out checking out {}, skipping the remaining revisions", rev.commit);
+                    partial = true;
+                    break;
+                },
+                Err(e) => {
+                    error!("Failed to checkout {}: {}", rev.commit, e);
+                    continue;
+                },
+            }
+
+            let Some(submodules) = self.submodules(dir) else {
+                return;
+            };
+            cache.set_submodules(submodules);
+            if let Err(e) = self.measure_disk(dir) {
+                error!("Stopped mining history of '{}': {}", self.repo.name, e);
+                break;
+            }
+
+            // Only files that changed need to be parsed again
+            let changed = previous.as_ref()
+                .and_then(|p| history::changed_files(dir, p, &rev.commit, timeout));
+            cache.set_changed(changed);
+
+            let start = Instant::now();
+            let Some(result) = self.mine_dir(dir, &mut cache, Some(&rev.commit)) else {
+                continue;
+            };
+            previous = Some(rev.commit.clone());
+
+            self.intern(&result, Some(&rev.commit));
+            self.crawl.db.rt.block_on(self.mark_usage(&result, Some(&rev.commit)));
+            self.crawl.db.rt.block_on(self.mark_failures(&result, Some(&rev.commit)));
+            self.crawl.db.rt.block_on(self.mark_log(&self.log_path(Some(&rev.commit)), Some(&rev.commit)));
+            self.crawl.db.rt.block_on(self.mark_revision(&rev, &result, start));
+
+            total.n_files += result.n_files;
+            total.n_success += result.n_success;
+            total.n_error += result.n_error;
+            total.n_skipped += result.n_skipped;
+            total.failures.merge(&result.failures);
+        }
+
+        partial |= total.n_skipped > 0;
+        self.crawl.db.rt.block_on(self.mark_as_mined(&total, partial));
+    }
+
+    /// Create a miner cache that is watched by the watchdog, & stops
+    /// compiling new files once the time budget of the repo runs out.
+    fn new_cache(&self) -> MineCache {
+        let mut cache = MineCache::new();
+        cache.set_progress(self.progress.clone());
+        self.crawl.watchdog.watch(&self.repo, self.progress.clone());
+        cache.set_deadline(self.deadline());
+        return cache;
+    }
+
+    /// Return the time the budget for this repo runs out, if it has one.
+    fn deadline(&self) -> Option<Instant> {
+        let budget = self.crawl.config.runner.max_repo_time?;
+        return Some(self.start + Duration::from_secs(budget));
+    }
+
+    /// Return true if the time budget of this repo has run out.
+    fn out_of_time(&self) -> bool {
+        return self.deadline().is_some_and(|d| Instant::now() >= d);
+    }
+
+    /// Record the size of the repository in DIR, & return an error if it is
+    /// larger than the configured limit.
+    fn measure_disk(&mut self, dir: &PathBuf) -> Result<(), FetchError> {
+        let size = disk::disk_usage(dir);
+        self.peak_disk = self.peak_disk.max(size);
+
+        let size = size / 1024 / 1024;
+        match self.crawl.config.runner.max_repo_size {
+            Some(max) if size > max => Err(FetchError::TooLarge(size)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the repository as skipped if RESULT hit a limit, & return true
+    /// if it can still be mined.
+    fn skip_if_limited(&self, result: Result<(), FetchError>) -> bool {
+        let Err(e) = result else {
+            return true;
+        };
+
+        if e.is_skip() {
+            info!("Skipping '{}': {}", self.repo.name, e);
+            self.crawl.db.rt.block_on(self.mark_as_skipped(&e.to_string()));
+        } else {
+            metrics::REPOS_FAILED.inc();
+        }
+        return false;
+    }
+
+    /// Initialize the configured submodules of the repository in DIR, & return
+    /// their paths. Returns None if the repository was skipped because a
+    /// submodule took too long to fetch.
+    fn submodules(&self, dir: &PathBuf) -> Option<Vec<PathBuf>> {
+        let Some(config) = &self.crawl.config.submodules else {
+            return Some(vec![]);
         };
 
-        // Setup the input
+        let result = submodule::init(dir, config, self.crawl.config.runner.clone_timeout);
+        match result {
+            Ok(paths) => Some(paths),
+            Err(e) => {
+                self.skip_if_limited(Err(e));
+                None
+            },
+        }
+    }
+
+    /// Return the path of the log of mining COMMIT, or the only revision
+    /// mined if not given.
+    fn log_path(&self, commit: Option<&str>) -> PathBuf {
+        let log_file = self.repo.name.replace("/", "-");
+        let log_file = match commit {
+            Some(c) => format!("{}-{}-{}.jsonl.gz", self.repo.id, log_file, &c[..c.len().min(12)]),
+            None => format!("{}-{}.jsonl.gz", self.repo.id, log_file),
+        };
+        return self.crawl.config.runner.log_dir.join(log_file);
+    }
+
+    /// Mine the files in DIR using the shared compile pool, & return once
+    /// every file has been compiled. If COMMIT is given, it is added to the
+    /// name of the log file.
+    fn mine_dir(&self, dir: &PathBuf, cache: &mut MineCache, commit: Option<&str>) -> Option<MineResult> {
+        let log_path = self.log_path(commit);
+
+        // Run the miner, blocking until it has finished so the repo isn't
+        // deleted before we have mined it
+        let config = self.crawl.config.clone();
+        let interface = self.interface.clone();
+        let result = self.crawl.pool.install(|| {
+            mine_cached(dir, &log_path, config, interface, cache)
+        });
+
+        return result.ok();
+    }
+
+    /// Call the user-supplied intern function on the results of mining COMMIT.
+    fn intern(&self, result: &MineResult, commit: Option<&str>) {
         info!("Interning results");
         let input = InternInput {
-            config: self.config,
+            config: self.crawl.config,
+            run_id: self.crawl.run_id,
             repo_id: self.repo.id,
+            commit,
             data: &result.data,
-            db: self.db,
+            db: self.crawl.db,
         };
 
-        // Call the user-supplied intern function
+        let start = Instant::now();
         match self.interface.intern(input) {
             Ok(_) => {},
             Err(e) => error!("Failed to intern: {:?}", e),
         }
+        metrics::INTERN_SECONDS.observe(start.elapsed());
+    }
 
-        self.db.rt.block_on(self.mark_as_mined(&result));
-        info!("Finished mining: '{}'", self.repo.name);
+    /// Return the commits of this repository that have already been mined.
+    async fn mined_revisions(&self) -> Result<HashSet<String>, sqlx::Error> {
+        let rows = sqlx::query::<Any>(
+            "select commit_sha from mined_revisions where repo_id = ?"
+        ).bind(self.repo.id)
+         .fetch_all(&self.crawl.db.pool)
+         .await?;
+
+        let mut acc = HashSet::new();
+        for row in rows {
+            let commit = row.try_get::<&[u8], usize>(0)?;
+            acc.insert(String::from_utf8_lossy(commit).to_string());
+        }
+
+        return Ok(acc);
     }
 
-    /// Mark the current repository as mined.
-    async fn mark_as_mined(&self, data: &MineResult) {
+    /// Record that a single revision has been mined.
+    async fn mark_revision(&self, rev: &Revision, data: &MineResult, start: Instant) {
+        let time = format!("{}", start.elapsed().as_millis());
+        let result = sqlx::query::<Any>(
+            "insert into mined_revisions
+             (repo_id, commit_sha, label, committed_at, n_files, n_success, n_error, time, run_id)
+             values (?, ?, ?, from_unixtime(?), ?, ?, ?, ?, ?)"
+        ).bind(self.repo.id)
+            .bind(rev.commit.clone())
+            .bind(rev.label.clone())
+            .bind(rev.time)
+            .bind(data.n_files)
+            .bind(data.n_success)
+            .bind(data.n_error)
+            .bind(time)
+            .bind(self.crawl.run_id)
+            .execute(&self.crawl.db.pool)
+            .await;
+
+        match result {
+            Ok(_) => {},
+            Err(e) => { error!("Failed to set revision as mined: {:?}", e) },
+        }
+    }
+
+    /// Record that the current repository was skipped because of REASON.
+    async fn mark_as_skipped(&self, reason: &str) {
+        let result = sqlx::query::<Any>(
+            "insert into skipped (repo_id, reason, skipped_at, run_id)
+             values (?, ?, now(), ?)"
+        ).bind(self.repo.id)
+            .bind(reason.to_string())
+            .bind(self.crawl.run_id)
+            .execute(&self.crawl.db.pool)
+            .await;
+
+        match result {
+            Ok(_) => {},
+            Err(e) => { error!("Failed to set repo as skipped: {:?}", e) },
+        }
+    }
+
+    /// Record the resources used to compile each file of COMMIT.
+    async fn mark_usage(&self, data: &MineResult, commit: Option<&str>) {
+        for (path, usage) in &data.usage {
+            let result = sqlx::query::<Any>(
+                "insert into compile_usage
+                 (repo_id, commit_sha, path, max_rss, cpu_time, run_id)
+                 values (?, ?, ?, ?, ?, ?)"
+            ).bind(self.repo.id)
+                .bind(commit.map(|c| c.to_string()))
+                .bind(path.to_string_lossy().to_string())
+                .bind(usage.max_rss as i64)
+                .bind(usage.cpu_time.as_millis() as i64)
+                .bind(self.crawl.run_id)
+                .execute(&self.crawl.db.pool)
+                .await;
+
+            if let Err(e) = result {
+                error!("Failed to add compile usage: {:?}", e);
+                return;
+            }
+        }
+    }
+
+    /// Record why attempts to compile the files of COMMIT failed, & the
+    /// headers & identifiers they were missing.
+    async fn mark_failures(&self, data: &MineResult, commit: Option<&str>) {
+        for (failure, n) in &data.failures.attempts {
+            let result = sqlx::query::<Any>(
+                "insert into failures
+                 (repo_id, commit_sha, category, n, run_id)
+                 values (?, ?, ?, ?, ?)"
+            ).bind(self.repo.id)
+                .bind(commit.map(|c| c.to_string()))
+                .bind(failure.name())
+                .bind(*n)
+                .bind(self.crawl.run_id)
+                .execute(&self.crawl.db.pool)
+                .await;
+
+            if let Err(e) = result {
+                error!("Failed to add failure counts: {:?}", e);
+                return;
+            }
+        }
+
+        for ((failure, subject), n) in &data.failures.subjects {
+            let result = sqlx::query::<Any>(
+                "insert into failure_subjects
+                 (repo_id, commit_sha, category, subject, n, run_id)
+                 values (?, ?, ?, ?, ?, ?)"
+            ).bind(self.repo.id)
+                .bind(commit.map(|c| c.to_string()))
+                .bind(failure.name())
+                .bind(subject.clone())
+                .bind(*n)
+                .bind(self.crawl.run_id)
+                .execute(&self.crawl.db.pool)
+                .await;
+
+            if let Err(e) = result {
+                error!("Failed to add failure subjects: {:?}", e);
+                return;
+            }
+        }
+    }
+
+    /// Record that the log of mining COMMIT is at PATH.
+    async fn mark_log(&self, path: &Path, commit: Option<&str>) {
+        let path = path.canonicalize().unwrap_or(path.to_path_buf());
+        let result = sqlx::query::<Any>(
+            "insert into repo_logs (repo_id, commit_sha, path, run_id)
+             values (?, ?, ?, ?)"
+        ).bind(self.repo.id)
+            .bind(commit.map(|c| c.to_string()))
+            .bind(path.to_string_lossy().to_string())
+            .bind(self.crawl.run_id)
+            .execute(&self.crawl.db.pool)
+            .await;
+
+        match result {
+            Ok(_) => {},
+            Err(e) => { error!("Failed to add log location: {:?}", e) },
+        }
+    }
+
+    /// Mark the current repository as mined. If PARTIAL, the time budget ran
+    /// out before every file was compiled.
+    async fn mark_as_mined(&self, data: &MineResult, partial: bool) {
+        metrics::REPOS_MINED.inc();
+
         // Set as mined
         let repo_id = self.repo.id;
-        let result = sqlx::query::<Any>("insert into mined values (?)")
-            .bind(repo_id)
-            .execute(&self.db.pool)
+        let checkout = self.repo.checkout.clone();
+        let result = sqlx::query::<Any>(
+            "insert into mined (repo_id, commit_sha, branch, cloned_at, run_id)
+             values (?, ?, ?, ?, ?)"
+        ).bind(repo_id)
+            .bind(checkout.as_ref().map(|c| c.commit.clone()))
+            .bind(checkout.as_ref().and_then(|c| c.branch.clone()))
+            .bind(checkout.as_ref().map(|c| c.cloned_at.clone()))
+            .bind(self.crawl.run_id)
+            .execute(&self.crawl.db.pool)
             .await;
 
         match result {
@@ -187,13 +716,20 @@ impl<'a> Runner<'a> {
 
         // Insert the statistics
         let time = format!("{}", self.start.elapsed().as_millis());
-        let result = sqlx::query::<Any>("insert into stats values (?, ?, ?, ?, ?)")
-            .bind(repo_id)
+        let result = sqlx::query::<Any>(
+            "insert into stats
+             (repo_id, n_files, n_success, n_error, time, peak_disk, partial, n_skipped, run_id)
+             values (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ).bind(repo_id)
             .bind(data.n_files)
             .bind(data.n_success)
             .bind(data.n_error)
             .bind(time)
-            .execute(&self.db.pool)
+            .bind(self.peak_disk as i64)
+            .bind(partial)
+            .bind(data.n_skipped)
+            .bind(self.crawl.run_id)
+            .execute(&self.crawl.db.pool)
             .await;
 
         match result {
@@ -206,5 +742,7 @@ impl<'a> Runner<'a> {
 impl Drop for Runner<'_> {
     fn drop(&mut self) {
         info!("Drop runner");
+        self.crawl.watchdog.unwatch(self.repo.id);
+        self.crawl.status.finish(self.repo.id);
     }
 }
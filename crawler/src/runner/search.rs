@@ -1,99 +1,202 @@
-use std::str::FromStr;
-use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-
 use crate::config::Config;
 use super::db::Database;
 use super::git::RepoData;
+use super::github::GitHubClient;
+use super::partition::{Coverage, Window};
+use super::progress::{Cursor, Progress};
+use super::source::{add_repos, RepoSource};
 
-use sqlx::{self, Row, Any};
-use reqwest;
-use reqwest::blocking::Client;
-use reqwest::header;
-use serde_json;
 use serde_json::value::Value;
-use log::{debug, info, warn};
-
-static USER_AGENT: &str = concat!(
-    env!("CARGO_PKG_NAME"),
-    "/",
-    env!("CARGO_PKG_VERSION"),
-);
+use log::{debug, error, info, warn};
 
 static PAGE_SIZE: usize = 100;
+static SEARCH_LIMIT: usize = 1000;    // Maximum results GitHub returns per query
 static INITIAL_MAX: usize = 10_000_000;
 
+/// How to treat windows searched by a previous run.
+#[derive(Clone, Copy, Debug)]
+pub enum SearchMode {
+    Fresh,      // Forget previous progress
+    Resume,     // Continue from where the last search stopped
+    Refresh,    // Search finished windows again, to find new repositories
+}
+
+/// Outcome of searching a single window.
+enum WindowResult {
+    Done(Coverage),         // All pages were added
+    Split(Window, Window),  // Too many results, search each half instead
+    Failed,                 // A page could not be fetched
+}
+
 pub struct Search<'a> {
     config: &'a Config,
     db: &'a Database,
+    mode: SearchMode,
+    progress: Progress<'a>,
 
-    client: Client,
+    client: GitHubClient,
 }
 
 impl<'a> Search<'a> {
-    /// Search for repositories.
-    pub fn new(config: &'a Config, db: &'a Database) -> Self {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "Authorization",
-            header::HeaderValue::from_str(
-                &format!("Bearer {}", config.runner.github_api_key)
-            ).unwrap()
-        );
-        headers.insert(
-            "X-GitHub-Api-Version",
-            header::HeaderValue::from_static("2022-11-28")
-        );
-
-        let client = reqwest::blocking::Client::builder()
-            .user_agent(USER_AGENT)
-            .default_headers(headers)
-            .build()
-            .unwrap();
-
-        // Run the search
-        let search = Self { config, db, client };
-        search.search();
-
-        return search;
+    /// Create a GitHub search.
+    pub fn new(config: &'a Config, db: &'a Database, mode: SearchMode) -> Self {
+        let client = match GitHubClient::new(config) {
+            Ok(c) => c,
+            Err(e) => panic!("Failed to create GitHub client: {}", e),
+        };
+
+        let progress = Progress::new(db);
+        return Self { config, db, mode, progress, client };
     }
 
     /// Search for all repos matching the search criteria.
-    pub fn search(&self) {
+    ///
+    /// Windows with at least `SEARCH_LIMIT` results are split until each part
+    /// can be fully paged through. Progress is stored in the database after
+    /// every page, so an interrupted search can be resumed.
+    pub fn search(&self) -> usize {
         let mut found = 0;
-        let mut page = 1;
+        let mut coverage: Vec<Coverage> = vec![];
 
-        let min = self.config.runner.min_stars;
-        let mut max = self.db.rt.block_on(self.min_stars());
+        let mut stack = match self.initial_windows(self.mode) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to load search progress: {}", e);
+                return 0;
+            }
+        };
 
-        loop {
+        // Search the highest star counts first
+        while let Some(cursor) = stack.pop() {
             info!("Found: '{}'", found);
-            // Get the next page of results
-            let (json, next_page) = self.get_page(min, max, page);
-
-            // Move to the next page
-            if next_page {
-                page = 1;
-                max = self.db.rt.block_on(self.min_stars());
+            debug!("Searching window: {:?}", cursor.window);
+
+            match self.search_window(cursor) {
+                WindowResult::Done(c) => {
+                    found += c.found;
+                    coverage.push(c);
+                },
+                WindowResult::Split(low, high) => {
+                    stack.push(Cursor::new(low));
+                    stack.push(Cursor::new(high));
+                },
+                WindowResult::Failed => {},
             }
+        }
 
-            // Otherwise, add the repositories to the database
-            else {
-                // Add the repos to the database
-                let repos = self.parse_results(json);
-                found += self.add_repos(repos);
+        // Report the coverage of the search
+        info!(
+            "Searched {} windows, found {} repositories",
+            coverage.len(), found
+        );
+        for c in coverage.iter().filter(|c| !c.complete) {
+            warn!(
+                "Incomplete window: {:?} ({} of {} results)",
+                c.window, c.found, c.total
+            );
+        }
 
-                // Move to the next page
-                page += 1;
-            }
+        return found;
+    }
+
+    /// Return the windows to start searching from.
+    fn initial_windows(&self, mode: SearchMode) -> Result<Vec<Cursor>, sqlx::Error> {
+        let root = Window::new(
+            self.config.runner.min_stars,
+            INITIAL_MAX,
+            self.config.search.created.as_deref(),
+        );
+
+        match mode {
+            SearchMode::Fresh => {
+                self.progress.clear()?;
+            },
+            SearchMode::Resume => {},
+            SearchMode::Refresh => {
+                self.progress.reset_finished()?;
+
+                // Also search the repositories created since the last search
+                let recent = self.progress.last_created()?
+                    .and_then(|last| root.created_after(last));
+                if let Some(window) = recent {
+                    info!("Adding window of new repositories: {:?}", window);
+                    self.progress.add(&window)?;
+                }
+            },
+        }
+
+        // Start from the root if nothing has been searched yet
+        if self.progress.is_empty()? {
+            self.progress.add(&root)?;
+        }
+
+        let pending = self.progress.pending()?;
+        info!("Searching {} pending windows", pending.len());
+        return Ok(pending);
+    }
 
-            // Stop running when the star interval is empty
-            if max <= min {
-                break;
+    /// Add all results in a single window.
+    fn search_window(&self, cursor: Cursor) -> WindowResult {
+        let Cursor { window, mut pages, mut total } = cursor;
+        let mut window_found = 0;
+        let mut complete = true;
+
+        // Get the first page, to see how large the window is
+        if pages == 0 {
+            let Some(json) = self.get_page(&window, 1) else {
+                return WindowResult::Failed;
+            };
+            total = json["total_count"].as_u64().unwrap_or(0) as usize;
+
+            // Split the window if it has too many results
+            if total >= SEARCH_LIMIT {
+                if let Some((low, high)) = window.split() {
+                    debug!("Splitting window with {} results", total);
+                    self.record(self.progress.split(&window, &low, &high));
+                    return WindowResult::Split(low, high);
+                }
+                warn!("Unable to split window: {:?}", window);
+                complete = false;
             }
+
+            window_found += self.add_page(json);
+            pages = 1;
+            self.record(self.progress.set_pages(&window, pages, total));
+        }
+
+        // Add the results from each remaining page
+        let last = total.min(SEARCH_LIMIT).div_ceil(PAGE_SIZE);
+        while pages < last {
+            let Some(json) = self.get_page(&window, pages + 1) else {
+                return WindowResult::Failed;
+            };
+            window_found += self.add_page(json);
+            pages += 1;
+            self.record(self.progress.set_pages(&window, pages, total));
+        }
+
+        self.record(self.progress.finish(&window, complete, pages, total));
+        return WindowResult::Done(
+            Coverage { window, total, found: window_found, complete }
+        );
+    }
+
+    /// Log any failure to record search progress.
+    fn record(&self, result: Result<(), sqlx::Error>) {
+        if let Err(e) = result {
+            error!("Failed to record search progress: {}", e);
         }
     }
 
+    /// Add the repositories in a page of results, & return how many were added.
+    fn add_page(&self, json: Value) -> usize {
+        if json["incomplete_results"].as_bool() == Some(true) {
+            warn!("GitHub returned incomplete results");
+        }
+        let repos = self.parse_results(json);
+        return add_repos(self.db, repos);
+    }
+
     /// Parse JSON into RepoData.
     fn parse_results(&self, json: Value) -> Vec<RepoData> {
         let mut acc = vec![];
@@ -109,130 +212,144 @@ impl<'a> Search<'a> {
         return acc;
     }
 
-    /// Add REPOS to the database. Ignore if they already exist.
-    fn add_repos(&self, repos: Vec<RepoData>) -> usize {
-        let mut count = 0;  // # of repos we added
-
-        for repo in repos {
-            debug!("Adding repo: {:?} {:?}", repo.name, repo.id);
-            match self.db.rt.block_on(self.add_repo(repo)) {
-                Ok(_) => { count += 1 },
-                // FIXME: Only ignore duplicate entries
-                Err(e) => {
-                    warn!("{}", e);
-                }
-            }
+    /// Get a single page of results, or None if the request failed.
+    fn get_page(&self, window: &Window, page_no: usize) -> Option<Value> {
+        let query = self.query(window, page_no);
+        match self.client.search_repositories(&query) {
+            Ok(json) => Some(json),
+            Err(e) => {
+                error!("Failed to get page {} of {:?}: {}", page_no, window, e);
+                None
+            },
         }
-
-        return count;
     }
 
-    /// Add a single repository to the database.
-    async fn add_repo(&self, repo: RepoData) -> Result<(), sqlx::Error> {
-        sqlx::query::<Any>("insert into repos values (?, ?, ?, ?)")
-            .bind(repo.id)
-            .bind(repo.name.clone())
-            .bind(repo.url.clone())
-            .bind(repo.stars)
-            .execute(&self.db.pool)
-            .await?;
-
-        Ok(())
-    }
+    /// Format the query string.
+    fn query(&self, window: &Window, page_no: usize) -> String {
+        let mut qualifiers = self.qualifiers();
+        qualifiers.extend(window.qualifiers());
 
-    /// Get a single page of results.
-    fn get_page(&self, min: usize, max: usize, page_no: usize) -> (Value, bool) {
-        // Get the next page
-        let query = self.query(min, max, page_no);
-        info!("{:#?}", query);
-        let result = self.client.get(query)
-                                .send()
-                                .unwrap();
-
-        // Perform any rate limiting
-        self.rate_limit(result.headers());
-
-        // Parse the results
-        let json: Value = serde_json::from_str(&result.text().unwrap()).unwrap();
-
-        // If the item key is present, we must go to the next page
-        match json["items"] {
-            Value::Null => {
-                return (json, true);
-            },
-            _ => {
-                return (json, false);
-            }
-        }
+        // Qualifiers can contain spaces & other reserved characters, so each
+        // is encoded before they are joined
+        let qualifiers: Vec<String> = qualifiers.iter()
+            .map(|q| form_urlencoded::byte_serialize(q.as_bytes()).collect())
+            .collect();
+
+        format!(
+            "q={}&{}&{}&per_page={}&page={}",
+            qualifiers.join("+"),
+            "sort=stars",
+            "order=desc",
+            PAGE_SIZE,
+            page_no,
+        )
     }
 
-    /// Perform any required rate-limiting.
-    fn rate_limit(&self, headers: &header::HeaderMap) {
-        let mut time = 0.0;
+    /// Return the search qualifiers that don't depend on the window.
+    fn qualifiers(&self) -> Vec<String> {
+        let search = &self.config.search;
+        let mut acc = vec![];
 
-        // If the "retry-after" header is present, sleep for the time it gives
-        if headers.contains_key("retry-after") {
-            time = self.from_header(headers, "retry-after");
+        // Multiple language qualifiers are OR'ed together by GitHub
+        let mut languages: Vec<_> = self.config.runner.languages.iter().collect();
+        languages.sort();
+        for lang in languages {
+            acc.push(format!("language:{}", lang));
         }
 
-        // Try rate limit headers
-        if self.from_header::<usize>(headers, "x-ratelimit-remaining") == 0 {
-            let now = Self::parse_time(SystemTime::now());
-            let then = self.from_header::<u64>(headers, "x-ratelimit-reset");
-            time = (then - now + 1) as f64;
+        for topic in &search.topics {
+            acc.push(format!("topic:{}", topic));
         }
-
-        // Sleep if needed
-        if time != 0.0 {
-            info!("Sleeping for {} seconds", time);
-            thread::sleep(Duration::from_secs_f64(time));
+        if let Some(license) = &search.license {
+            acc.push(format!("license:{}", license));
+        }
+        if let Some(date) = &search.pushed_after {
+            acc.push(format!("pushed:>{}", date));
         }
+        if let Some(range) = &search.size {
+            acc.push(format!("size:{}", range));
+        }
+        if search.exclude_archived {
+            acc.push("archived:false".to_string());
+        }
+        if search.exclude_forks {
+            acc.push("fork:false".to_string());
+        }
+
+        return acc;
     }
+}
 
-    /// Return TIME as an integer (unix time).
-    fn parse_time(time: SystemTime) -> u64 {
-        time.duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
+impl RepoSource for Search<'_> {
+    fn name(&self) -> String {
+        return "github".to_string();
     }
 
-    /// Get a NAME from HEADERS & parse it to a given type
-    fn from_header<T: FromStr>(&self, headers: &header::HeaderMap, name: &str) -> T {
-        match headers[name].to_str().unwrap().parse::<T>() {
-            Ok(s) => s,
-            _ => panic!(),
-        }
+    fn collect(&self) -> usize {
+        return self.search();
     }
+}
 
-    /// Format the query.
-    fn query(&self, min: usize, max: usize, page_no: usize) -> String {
-        format!(
-            "https://api.github.com/search/repositories?q=language:c+stars:{}..{}&{}&{}&per_page={}&page={}",
-            min,
-            max,
-            "sort=stars",
-            "order=desc",
-            PAGE_SIZE,
-            page_no,
-        )
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mock::{self, MockServer, Response};
+
+    use serde_json::json;
+    use sqlx::Any;
+
+    /// Ids of the repositories returned by the mock server.
+    const FIRST_ID: i64 = 1_900_000_000;
+
+    /// Answer a search with 250 results, one repository on each page.
+    fn search_page(path: &str) -> Response {
+        let page: i64 = path.rsplit_once("page=").unwrap().1.parse().unwrap();
+        let body = json!({
+            "total_count": 250,
+            "incomplete_results": false,
+            "items": [{
+                "id": FIRST_ID + page,
+                "full_name": format!("mock/repo-{}", page),
+                "clone_url": format!("https://example.com/mock/repo-{}.git", page),
+                "stargazers_count": 20,
+                "size": 100,
+            }],
+        });
+        return Response::json(body.to_string());
     }
 
-    /// Return the minimum star count of any repository.
-    async fn min_stars(&self) -> usize {
-        let row = sqlx::query("select min(stars) from repos")
-            .fetch_one(&self.db.pool)
-            .await;
-
-        match row {
-            Ok(row) => {
-                match row.try_get::<i64, usize>(0) {
-                    Ok(min) => min as usize,
-                    Err(_) => INITIAL_MAX,
-                }
-            },
-            Err(_) => {
-                return INITIAL_MAX;
-            }
-        }
+    #[test]
+    fn resume_from_search_progress() {
+        let server = MockServer::start(search_page);
+        let config = mock::config(&format!(
+            "[search]\napi_base_url = \"{}\"\ncreated = \"2020-01-01..2020-12-31\"\n",
+            server.url
+        ));
+        let Some(db) = mock::database(&config) else {
+            return;
+        };
+        db.rt.block_on(
+            sqlx::query::<Any>("delete from repos where repo_id > ? and repo_id <= ?")
+                .bind(FIRST_ID)
+                .bind(FIRST_ID + 3)
+                .execute(&db.pool)
+        ).unwrap();
+
+        // An earlier search was interrupted after the first page
+        let progress = Progress::new(&db);
+        progress.clear().unwrap();
+        let root = Window::new(10, INITIAL_MAX, Some("2020-01-01..2020-12-31"));
+        progress.add(&root).unwrap();
+        progress.set_pages(&root, 1, 250).unwrap();
+
+        let found = Search::new(&config, &db, SearchMode::Resume).search();
+
+        // Only the remaining pages are fetched
+        let pages: Vec<String> = server.requests().iter()
+            .map(|p| p.rsplit_once("page=").unwrap().1.to_string())
+            .collect();
+        assert_eq!(pages, ["2", "3"]);
+        assert_eq!(found, 2);
+        assert!(progress.pending().unwrap().is_empty());
     }
 }
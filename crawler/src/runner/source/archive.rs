@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::runner::db::Database;
+use crate::runner::git::{is_archive, RepoData};
+use super::{add_repos, content_id, RepoSource};
+
+use log::{debug, error};
+
+/// Source archives, either a single archive or a directory of them.
+///
+/// Archives are extracted in place of a clone, & are identified by a hash of
+/// their contents, so moving an archive doesn't cause it to be mined again.
+pub struct ArchiveSource<'a> {
+    db: &'a Database,
+    path: &'a PathBuf,
+}
+
+impl<'a> ArchiveSource<'a> {
+    pub fn new(db: &'a Database, path: &'a PathBuf) -> Self {
+        return Self { db, path };
+    }
+
+    /// Create a repository from the archive at PATH.
+    fn repo(path: &Path) -> Option<RepoData> {
+        let abs = match path.canonicalize() {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Failed to find archive {:?}: {}", path, e);
+                return None;
+            },
+        };
+        let url = abs.to_str()?;
+        let id = match content_id(&abs) {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to read archive {:?}: {}", abs, e);
+                return None;
+            },
+        };
+
+        // Remove the archive extensions from the name
+        let file_name = abs.file_name()?.to_string_lossy();
+        let name = file_name.split(".tar").next().unwrap();
+        let name = name.trim_end_matches(".tgz").trim_end_matches(".zip");
+
+        return Some(RepoData::new(id, name, url, 0));
+    }
+}
+
+impl RepoSource for ArchiveSource<'_> {
+    fn name(&self) -> String {
+        return format!("archive:{}", self.path.display());
+    }
+
+    fn collect(&self) -> usize {
+        // A single archive
+        if self.path.is_file() {
+            let repos = Self::repo(self.path).into_iter().collect();
+            return add_repos(self.db, repos);
+        }
+
+        // A directory of archives
+        let entries = match fs::read_dir(self.path) {
+            Ok(e) => e,
+            Err(e) => {
+                error!("Failed to read directory {:?}: {}", self.path, e);
+                return 0;
+            },
+        };
+
+        let mut repos = vec![];
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() || !is_archive(&path.to_string_lossy()) {
+                debug!("Skipping non-archive {:?}", path);
+                continue;
+            }
+            if let Some(repo) = Self::repo(&path) {
+                repos.push(repo);
+            }
+        }
+
+        return add_repos(self.db, repos);
+    }
+}
@@ -0,0 +1,490 @@
+use crate::config::Config;
+use crate::runner::db::Database;
+use crate::runner::git::RepoData;
+use crate::runner::github::{from_header, parse_time};
+use super::{add_repos, stable_id, RepoSource};
+
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use serde_json::value::Value;
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+static PAGE_SIZE: usize = 50;
+static RATE_LIMIT_WAIT: u64 = 60;       // Seconds, if the forge doesn't say
+
+/// Kind of self-hosted forge.
+#[derive(Clone, Copy, Debug)]
+pub enum Forge {
+    GitLab,
+    Gitea,
+}
+
+/// Repositories on a GitLab or Gitea instance, sorted by stars.
+pub struct ForgeSource<'a> {
+    db: &'a Database,
+    min_stars: i64,
+    client: ForgeClient,
+}
+
+impl<'a> ForgeSource<'a> {
+    pub fn new(config: &'a Config, db: &'a Database, forge: Forge, url: &str, token: &Option<String>) -> Self {
+        let client = ForgeClient::new(config, forge, url, token);
+        return Self { db, min_stars: config.runner.min_stars as i64, client };
+    }
+}
+
+/// Client for the API of a GitLab or Gitea instance.
+struct ForgeClient {
+    forge: Forge,
+    url: String,
+    token: Option<String>,
+    languages: Vec<&'static str>,   // As named by the forge
+    max_retries: usize,
+    client: Client,
+}
+
+impl ForgeClient {
+    fn new(config: &Config, forge: Forge, url: &str, token: &Option<String>) -> Self {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap();
+
+        return Self {
+            forge,
+            url: url.trim_end_matches('/').to_string(),
+            token: token.clone(),
+            languages: forge_languages(&config.runner.languages),
+            max_retries: config.search.max_retries,
+            client,
+        };
+    }
+
+    /// Return the language of each search. GitLab can filter by language, but
+    /// only one at a time, so each language is searched separately.
+    fn searches(&self) -> Vec<Option<&'static str>> {
+        match self.forge {
+            Forge::GitLab if !self.languages.is_empty() => {
+                self.languages.iter().map(|l| Some(*l)).collect()
+            },
+            _ => vec![None],
+        }
+    }
+
+    /// Format the URL of a single page of results, in LANG if given.
+    fn query(&self, lang: Option<&str>, page_no: usize) -> String {
+        match self.forge {
+            Forge::GitLab => {
+                let filter = match lang {
+                    Some(lang) => format!(
+                        "with_programming_language={}&",
+                        form_urlencoded::byte_serialize(lang.as_bytes()).collect::<String>()
+                    ),
+                    None => String::new(),
+                };
+                format!(
+                    "{}/api/v4/projects?{}order_by=star_count&sort=desc&per_page={}&page={}",
+                    self.url, filter, PAGE_SIZE, page_no,
+                )
+            },
+            Forge::Gitea => {
+                format!(
+                    "{}/api/v1/repos/search?sort=stars&order=desc&limit={}&page={}",
+                    self.url, PAGE_SIZE, page_no,
+                )
+            },
+        }
+    }
+
+    /// Get a single page of results, waiting out any rate limits.
+    fn get_page(&self, lang: Option<&str>, page_no: usize) -> Option<Vec<Value>> {
+        let query = self.query(lang, page_no);
+        info!("{:#?}", query);
+
+        let mut retries = 0;
+        let response = loop {
+            let mut request = self.client.get(&query);
+            if let Some(token) = &self.token {
+                request = match self.forge {
+                    Forge::GitLab => request.header("PRIVATE-TOKEN", token),
+                    Forge::Gitea => request.header("Authorization", format!("token {}", token)),
+                };
+            }
+
+            let response = request.send();
+            let wait = match &response {
+                Ok(r) if retries < self.max_retries => rate_limit_wait(r),
+                _ => None,
+            };
+            let Some(wait) = wait else {
+                break response;
+            };
+
+            warn!("Rate limited by {}, retrying in {} seconds", self.url, wait);
+            thread::sleep(Duration::from_secs(wait));
+            retries += 1;
+        };
+
+        let json: Value = match response.and_then(|r| r.error_for_status()) {
+            Ok(r) => match r.json() {
+                Ok(j) => j,
+                Err(e) => {
+                    error!("Invalid response from {}: {}", self.url, e);
+                    return None;
+                },
+            },
+            Err(e) => {
+                error!("Request to {} failed: {}", self.url, e);
+                return None;
+            },
+        };
+
+        // Gitea wraps the results in a data field
+        let items = match self.forge {
+            Forge::GitLab => &json,
+            Forge::Gitea => &json["data"],
+        };
+
+        match items {
+            Value::Array(items) => Some(items.clone()),
+            _ => {
+                error!("Unexpected response from {}: {}", self.url, json);
+                None
+            },
+        }
+    }
+
+    /// Parse a single project.
+    fn parse(&self, item: &Value) -> Option<RepoData> {
+        let (name, url, stars) = match self.forge {
+            Forge::GitLab => (
+                item["path_with_namespace"].as_str()?,
+                item["http_url_to_repo"].as_str()?,
+                item["star_count"].as_i64()?,
+            ),
+            Forge::Gitea => (
+                item["full_name"].as_str()?,
+                item["clone_url"].as_str()?,
+                item["stars_count"].as_i64()?,
+            ),
+        };
+
+        // Gitea reports the size in KB, like GitHub
+        let mut repo = RepoData::new(stable_id(url), name, url, stars);
+        if let Forge::Gitea = self.forge {
+            repo.size = item["size"].as_i64();
+        }
+        return Some(repo);
+    }
+
+    /// Return true if ITEM uses one of the configured languages. Only Gitea
+    /// reports the language of each repository.
+    fn has_language(&self, item: &Value) -> bool {
+        match self.forge {
+            Forge::GitLab => true,
+            Forge::Gitea if self.languages.is_empty() => true,
+            Forge::Gitea => {
+                let lang = item["language"].as_str().unwrap_or("");
+                self.languages.iter().any(|l| l.eq_ignore_ascii_case(lang))
+            },
+        }
+    }
+
+    /// Pass the repositories with at least MIN_STARS on each page to ADD,
+    /// until there are none left, & return the sum of what ADD returns.
+    fn for_each_page(&self, min_stars: i64, mut add: impl FnMut(Vec<RepoData>) -> usize) -> usize {
+        let mut found = 0;
+        let mut seen = HashSet::new();     // Repos in several languages
+
+        for lang in self.searches() {
+            let mut page = 1;
+            while let Some(items) = self.get_page(lang, page) {
+                if items.is_empty() {
+                    break;
+                }
+
+                let mut repos = vec![];
+                let mut below_min = false;
+                for item in &items {
+                    let Some(repo) = self.parse(item) else {
+                        warn!("Failed to parse repository: {}", item);
+                        continue;
+                    };
+
+                    // Results are sorted by stars, so we can stop early
+                    if repo.stars < min_stars {
+                        below_min = true;
+                        break;
+                    }
+
+                    if self.has_language(item) && seen.insert(repo.id) {
+                        repos.push(repo);
+                    }
+                }
+
+                found += add(repos);
+                if below_min {
+                    break;
+                }
+                page += 1;
+            }
+        }
+
+        return found;
+    }
+}
+
+/// Return the names the forges use for the configured LANGUAGES, in order.
+/// Both use the names of GitHub's linguist.
+fn forge_languages(languages: &HashSet<String>) -> Vec<&'static str> {
+    let mut acc: Vec<&'static str> = languages.iter()
+        .filter_map(|l| match l.as_str() {
+            "c" => Some("C"),
+            "cpp" => Some("C++"),
+            _ => None,
+        })
+        .collect();
+    acc.sort();
+    return acc;
+}
+
+/// Return how many seconds to wait if RESPONSE is a rate limit, or None if
+/// it isn't.
+///
+/// Both forges answer with 429 & `Retry-After`. GitLab also sends
+/// `RateLimit-Reset`, & uses 403 once `RateLimit-Remaining` runs out.
+fn rate_limit_wait(response: &Response) -> Option<u64> {
+    let headers = response.headers();
+    let exhausted = from_header::<u64>(headers, "ratelimit-remaining") == Some(0);
+    let limited = response.status() == StatusCode::TOO_MANY_REQUESTS
+        || (response.status() == StatusCode::FORBIDDEN && exhausted);
+    if !limited {
+        return None;
+    }
+
+    if let Some(secs) = from_header::<u64>(headers, "retry-after") {
+        return Some(secs);
+    }
+    if let Some(reset) = from_header::<u64>(headers, "ratelimit-reset") {
+        return Some(reset.saturating_sub(parse_time(SystemTime::now())));
+    }
+    return Some(RATE_LIMIT_WAIT);
+}
+
+impl RepoSource for ForgeSource<'_> {
+    fn name(&self) -> String {
+        let kind = match self.client.forge {
+            Forge::GitLab => "gitlab",
+            Forge::Gitea => "gitea",
+        };
+        return format!("{}:{}", kind, self.client.url);
+    }
+
+    fn collect(&self) -> usize {
+        return self.client.for_each_page(self.min_stars, |repos| add_repos(self.db, repos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::mock::{self, MockServer, Response};
+
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Return the page number requested by PATH.
+    fn page_of(path: &str) -> usize {
+        let query = path.split_once('?').unwrap().1;
+        return query.split('&')
+            .find_map(|p| p.strip_prefix("page="))
+            .unwrap()
+            .parse()
+            .unwrap();
+    }
+
+    fn gitea_repo(name: &str, stars: i64, language: &str) -> Value {
+        return json!({
+            "full_name": name,
+            "clone_url": format!("https://gitea.example.com/{}.git", name),
+            "stars_count": stars,
+            "language": language,
+            "size": 10,
+        });
+    }
+
+    fn gitlab_repo(name: &str, stars: i64) -> Value {
+        return json!({
+            "path_with_namespace": name,
+            "http_url_to_repo": format!("https://gitlab.example.com/{}.git", name),
+            "star_count": stars,
+        });
+    }
+
+    /// Collect every repository from SERVER, returning their names.
+    fn collect(server: &MockServer, forge: Forge, max_retries: usize) -> Vec<String> {
+        return collect_languages(server, forge, max_retries, &["c"]);
+    }
+
+    /// Collect every repository in LANGUAGES from SERVER, returning their
+    /// names.
+    fn collect_languages(server: &MockServer, forge: Forge, max_retries: usize, languages: &[&str]) -> Vec<String> {
+        let mut config = mock::config(&format!("[search]\nmax_retries = {}\n", max_retries));
+        config.runner.languages = languages.iter().map(|l| l.to_string()).collect();
+        let client = ForgeClient::new(&config, forge, &server.url, &None);
+
+        let mut names = vec![];
+        client.for_each_page(10, |repos| {
+            let n = repos.len();
+            names.extend(repos.into_iter().map(|r| r.name.clone()));
+            n
+        });
+        return names;
+    }
+
+    #[test]
+    fn gitea_pages_until_empty() {
+        let server = MockServer::start(|path| {
+            let items = match page_of(path) {
+                1 => vec![gitea_repo("a/one", 30, "C"), gitea_repo("a/two", 20, "Go")],
+                2 => vec![gitea_repo("a/three", 15, "C")],
+                _ => vec![],
+            };
+            Response::json(json!({ "ok": true, "data": items }).to_string())
+        });
+
+        // Repositories in other languages are left out
+        assert_eq!(collect(&server, Forge::Gitea, 0), ["a/one", "a/three"]);
+        let pages: Vec<usize> = server.requests().iter().map(|p| page_of(p)).collect();
+        assert_eq!(pages, [1, 2, 3]);
+    }
+
+    #[test]
+    fn gitlab_stops_below_min_stars() {
+        let server = MockServer::start(|path| {
+            let items = match page_of(path) {
+                1 => vec![gitlab_repo("g/one", 50), gitlab_repo("g/two", 40)],
+                2 => vec![gitlab_repo("g/three", 12), gitlab_repo("g/four", 9)],
+                _ => vec![gitlab_repo("g/five", 5)],
+            };
+            Response::json(json!(items).to_string())
+        });
+
+        assert_eq!(collect(&server, Forge::GitLab, 0), ["g/one", "g/two", "g/three"]);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn retry_after_rate_limit() {
+        let count = AtomicUsize::new(0);
+        let server = MockServer::start(move |path| {
+            // Limit the first request to each page
+            if count.fetch_add(1, Ordering::SeqCst) % 2 == 0 {
+                return Response {
+                    status: 429,
+                    headers: vec![("Retry-After", "0".to_string())],
+                    body: String::new(),
+                };
+            }
+            let items = match page_of(path) {
+                1 => vec![gitea_repo("a/one", 30, "C")],
+                _ => vec![],
+            };
+            Response::json(json!({ "ok": true, "data": items }).to_string())
+        });
+
+        assert_eq!(collect(&server, Forge::Gitea, 1), ["a/one"]);
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn gitlab_waits_for_reset() {
+        let count = AtomicUsize::new(0);
+        let server = MockServer::start(move |path| {
+            if count.fetch_add(1, Ordering::SeqCst) == 0 {
+                let now = parse_time(SystemTime::now());
+                return Response {
+                    status: 403,
+                    headers: vec![
+                        ("RateLimit-Remaining", "0".to_string()),
+                        ("RateLimit-Reset", now.to_string()),
+                    ],
+                    body: String::new(),
+                };
+            }
+            let items = match page_of(path) {
+                1 => vec![gitlab_repo("g/one", 50)],
+                _ => vec![],
+            };
+            Response::json(json!(items).to_string())
+        });
+
+        assert_eq!(collect(&server, Forge::GitLab, 1), ["g/one"]);
+    }
+
+    #[test]
+    fn give_up_after_retries() {
+        let server = MockServer::start(|_| Response {
+            status: 429,
+            headers: vec![("Retry-After", "0".to_string())],
+            body: String::new(),
+        });
+
+        assert!(collect(&server, Forge::Gitea, 2).is_empty());
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn forbidden_without_rate_limit_isnt_retried() {
+        let server = MockServer::start(|_| Response {
+            status: 403,
+            headers: vec![],
+            body: String::new(),
+        });
+
+        assert!(collect(&server, Forge::GitLab, 2).is_empty());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn gitea_matches_forge_language_names() {
+        let server = MockServer::start(|path| {
+            let items = match page_of(path) {
+                1 => vec![
+                    gitea_repo("a/one", 30, "C"),
+                    gitea_repo("a/two", 20, "C++"),
+                    gitea_repo("a/three", 15, "Go"),
+                ],
+                _ => vec![],
+            };
+            Response::json(json!({ "ok": true, "data": items }).to_string())
+        });
+
+        assert_eq!(collect_languages(&server, Forge::Gitea, 0, &["cpp"]), ["a/two"]);
+        assert_eq!(collect_languages(&server, Forge::Gitea, 0, &["c", "cpp"]), ["a/one", "a/two"]);
+    }
+
+    #[test]
+    fn gitlab_searches_each_language() {
+        let server = MockServer::start(|path| {
+            let items = match (page_of(path), path.contains("language=C%2B%2B&")) {
+                (1, false) => vec![gitlab_repo("g/c", 50), gitlab_repo("g/both", 40)],
+                (1, true) => vec![gitlab_repo("g/both", 40), gitlab_repo("g/cpp", 30)],
+                _ => vec![],
+            };
+            Response::json(json!(items).to_string())
+        });
+
+        // Repos in both languages are only collected once
+        let names = collect_languages(&server, Forge::GitLab, 0, &["cpp", "c"]);
+        assert_eq!(names, ["g/c", "g/both", "g/cpp"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].contains("with_programming_language=C&"));
+        assert!(requests[2].contains("with_programming_language=C%2B%2B&"));
+    }
+}
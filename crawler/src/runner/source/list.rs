@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::runner::db::Database;
+use crate::runner::git::RepoData;
+use super::{add_repos, name_from_url, stable_id, RepoSource};
+
+use log::{error, warn};
+
+/// Repositories listed in a file.
+///
+/// Each line is either a clone URL, or a CSV record of `url,name,stars,ref`
+/// where the name, stars, & ref are optional. The ref is a commit or tag to
+/// mine instead of the default branch. Fields containing commas can be quoted
+/// with `"`. Blank lines & lines starting with `#` are ignored.
+pub struct ListSource<'a> {
+    db: &'a Database,
+    path: &'a PathBuf,
+}
+
+impl<'a> ListSource<'a> {
+    pub fn new(db: &'a Database, path: &'a PathBuf) -> Self {
+        return Self { db, path };
+    }
+
+    /// Parse a single line of the file.
+    fn parse_line(line: &str) -> Option<RepoData> {
+        let Some(fields) = split_fields(line) else {
+            warn!("Unterminated quote in repository list: {}", line);
+            return None;
+        };
+        let fields: Vec<&str> = fields.iter().map(|f| f.trim()).collect();
+
+        // Skip an optional CSV header
+        let url = fields[0];
+        if url == "url" {
+            return None;
+        }
+
+        let name = match fields.get(1) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => name_from_url(url),
+        };
+
+        let stars = match fields.get(2) {
+            Some(stars) if !stars.is_empty() => match stars.parse::<i64>() {
+                Ok(s) => s,
+                Err(_) => {
+                    warn!("Invalid star count '{}' for '{}'", stars, url);
+                    0
+                },
+            },
+            _ => 0,
+        };
+
+        let mut repo = RepoData::new(stable_id(url), &name, url, stars);
+        repo.revision = fields.get(3)
+            .filter(|r| !r.is_empty())
+            .map(|r| r.to_string());
+
+        return Some(repo);
+    }
+}
+
+/// Split a CSV LINE into its fields, removing the quotes around quoted
+/// fields. Returns None if a quote isn't closed.
+fn split_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            // A doubled quote inside a quoted field is a literal quote
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    return Some(fields);
+}
+
+impl RepoSource for ListSource<'_> {
+    fn name(&self) -> String {
+        return format!("list:{}", self.path.display());
+    }
+
+    fn collect(&self) -> usize {
+        let contents = match fs::read_to_string(self.path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to read repository list {:?}: {}", self.path, e);
+                return 0;
+            },
+        };
+
+        let repos: Vec<RepoData> = contents.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(Self::parse_line)
+            .collect();
+
+        return add_repos(self.db, repos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(String, String, i64, Option<String>)> {
+        let repo = ListSource::parse_line(line)?;
+        return Some((repo.url.clone(), repo.name.clone(), repo.stars, repo.revision.clone()));
+    }
+
+    #[test]
+    fn parse_url_only() {
+        let (url, name, stars, rev) = parse("https://example.com/owner/repo.git").unwrap();
+        assert_eq!(url, "https://example.com/owner/repo.git");
+        assert_eq!(name, "owner/repo");
+        assert_eq!((stars, rev), (0, None));
+    }
+
+    #[test]
+    fn parse_full_record() {
+        let line = "https://example.com/a/b.git, my-name, 42, v1.0";
+        let parsed = parse(line).unwrap();
+        assert_eq!(parsed.1, "my-name");
+        assert_eq!((parsed.2, parsed.3), (42, Some("v1.0".to_string())));
+    }
+
+    #[test]
+    fn parse_quoted_fields() {
+        let line = r#""https://example.com/a/b.git","Foo, ""Bar"" & Baz",7"#;
+        let (url, name, stars, _) = parse(line).unwrap();
+        assert_eq!(url, "https://example.com/a/b.git");
+        assert_eq!(name, r#"Foo, "Bar" & Baz"#);
+        assert_eq!(stars, 7);
+    }
+
+    #[test]
+    fn reject_unterminated_quote() {
+        assert!(parse(r#"https://example.com/a/b.git,"Foo, Bar,7"#).is_none());
+    }
+
+    #[test]
+    fn skip_header() {
+        assert!(parse("url,name,stars,ref").is_none());
+    }
+
+    #[test]
+    fn invalid_stars_are_zero() {
+        assert_eq!(parse("https://example.com/a/b.git,,many").unwrap().2, 0);
+    }
+
+    #[test]
+    fn empty_fields_use_defaults() {
+        let (_, name, stars, rev) = parse("https://example.com/a/b.git,,,").unwrap();
+        assert_eq!(name, "a/b");
+        assert_eq!((stars, rev), (0, None));
+    }
+}
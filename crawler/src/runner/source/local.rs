@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::runner::db::Database;
+use crate::runner::git::RepoData;
+use super::{add_repos, stable_id, RepoSource};
+
+use log::{debug, error};
+
+/// Bare or working repositories in a local directory.
+///
+/// Each sub-directory that is a repository is cloned from the local path, so
+/// the original is never modified.
+pub struct LocalSource<'a> {
+    db: &'a Database,
+    path: &'a PathBuf,
+}
+
+impl<'a> LocalSource<'a> {
+    pub fn new(db: &'a Database, path: &'a PathBuf) -> Self {
+        return Self { db, path };
+    }
+
+    /// Return true if DIR is a bare or working git repository.
+    fn is_repo(dir: &Path) -> bool {
+        let working = dir.join(".git").exists();
+        let bare = dir.join("HEAD").is_file() && dir.join("objects").is_dir();
+        return working || bare;
+    }
+}
+
+impl RepoSource for LocalSource<'_> {
+    fn name(&self) -> String {
+        return format!("local:{}", self.path.display());
+    }
+
+    fn collect(&self) -> usize {
+        let entries = match fs::read_dir(self.path) {
+            Ok(e) => e,
+            Err(e) => {
+                error!("Failed to read directory {:?}: {}", self.path, e);
+                return 0;
+            },
+        };
+
+        let mut repos = vec![];
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            if !dir.is_dir() || !Self::is_repo(&dir) {
+                debug!("Skipping non-repository {:?}", dir);
+                continue;
+            }
+
+            // Use a file URL, so shallow clones are respected
+            let Ok(abs) = dir.canonicalize() else { continue; };
+            let url = format!("file://{}", abs.display());
+
+            let name = entry.file_name().to_string_lossy().trim_end_matches(".git").to_string();
+            repos.push(RepoData::new(stable_id(&url), &name, &url, 0));
+        }
+
+        return add_repos(self.db, repos);
+    }
+}
@@ -0,0 +1,171 @@
+mod archive;
+mod forge;
+mod list;
+mod local;
+
+use crate::config::{Config, Source};
+use super::db::Database;
+use super::git::RepoData;
+use super::search::{Search, SearchMode};
+use archive::ArchiveSource;
+use forge::{Forge, ForgeSource};
+use list::ListSource;
+use local::LocalSource;
+
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use sqlx::{self, Any};
+use log::{debug, info, warn};
+
+/// Somewhere that repositories can be found.
+pub trait RepoSource {
+    /// Name of the source, for logging.
+    fn name(&self) -> String;
+
+    /// Add all repositories from this source to the `repos` table, & return
+    /// how many were added.
+    fn collect(&self) -> usize;
+}
+
+/// Create each repository source in CONFIG.
+pub fn from_config<'a>(config: &'a Config, db: &'a Database, mode: SearchMode) -> Vec<Box<dyn RepoSource + 'a>> {
+    // Search GitHub by default
+    if config.sources.is_empty() {
+        return vec![Box::new(Search::new(config, db, mode))];
+    }
+
+    let mut acc: Vec<Box<dyn RepoSource + 'a>> = vec![];
+    for source in &config.sources {
+        let source: Box<dyn RepoSource + 'a> = match source {
+            Source::Github => {
+                Box::new(Search::new(config, db, mode))
+            },
+            Source::List { path } => {
+                Box::new(ListSource::new(db, path))
+            },
+            Source::Local { path } => {
+                Box::new(LocalSource::new(db, path))
+            },
+            Source::Archive { path } => {
+                Box::new(ArchiveSource::new(db, path))
+            },
+            Source::Gitlab { url, token } => {
+                Box::new(ForgeSource::new(config, db, Forge::GitLab, url, token))
+            },
+            Source::Gitea { url, token } => {
+                Box::new(ForgeSource::new(config, db, Forge::Gitea, url, token))
+            },
+        };
+        acc.push(source);
+    }
+
+    return acc;
+}
+
+/// Collect the repositories from every source in CONFIG.
+pub fn collect_all(config: &Config, db: &Database, mode: SearchMode) {
+    for source in from_config(config, db, mode) {
+        info!("Collecting repositories from '{}'", source.name());
+        let found = source.collect();
+        info!("Added {} repositories from '{}'", found, source.name());
+    }
+}
+
+/// Return a stable id for a repository that didn't come from GitHub.
+///
+/// GitHub ids are always positive, so these ids are negative to avoid
+/// collisions.
+pub fn stable_id(url: &str) -> i64 {
+    return id_from_hash(&Sha256::digest(url.as_bytes()));
+}
+
+/// Return a stable id for the archive at PATH, from a hash of its contents,
+/// so the same archive has the same id wherever it is.
+pub fn content_id(path: &Path) -> io::Result<i64> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    return Ok(id_from_hash(&hasher.finalize()));
+}
+
+/// Return a negative id from the first bytes of HASH.
+///
+/// 63 bits are used, so collisions are unlikely even with millions of
+/// repositories.
+fn id_from_hash(hash: &[u8]) -> i64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+
+    let id = (u64::from_be_bytes(bytes) & 0x7fff_ffff_ffff_ffff) as i64;
+    return -id - 1;
+}
+
+/// Return a human readable name from a clone URL, for example
+/// "https://host/owner/name.git" becomes "owner/name".
+pub fn name_from_url(url: &str) -> String {
+    let path = url.trim_end_matches('/').trim_end_matches(".git");
+    let parts: Vec<_> = path.rsplit(['/', ':']).take(2).collect();
+    let parts: Vec<_> = parts.into_iter().rev().collect();
+    return parts.join("/");
+}
+
+/// Add REPOS to the database. Ignore if they already exist.
+pub fn add_repos(db: &Database, repos: Vec<RepoData>) -> usize {
+    let mut count = 0;  // # of repos we added
+
+    for repo in repos {
+        debug!("Adding repo: {:?} {:?}", repo.name, repo.id);
+        match db.rt.block_on(add_repo(db, repo)) {
+            Ok(_) => { count += 1 },
+            // FIXME: Only ignore duplicate entries
+            Err(e) => {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    return count;
+}
+
+/// Add a single repository to the database.
+async fn add_repo(db: &Database, repo: RepoData) -> Result<(), sqlx::Error> {
+    sqlx::query::<Any>(
+        "insert into repos (repo_id, name, clone_url, stars, revision, size)
+         values (?, ?, ?, ?, ?, ?)"
+    ).bind(repo.id)
+        .bind(repo.name.clone())
+        .bind(repo.url.clone())
+        .bind(repo.stars)
+        .bind(repo.revision.clone())
+        .bind(repo.size)
+        .execute(&db.pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_from_https_url() {
+        assert_eq!(name_from_url("https://example.com/owner/repo.git"), "owner/repo");
+        assert_eq!(name_from_url("https://example.com/owner/repo/"), "owner/repo");
+    }
+
+    #[test]
+    fn name_from_ssh_url() {
+        assert_eq!(name_from_url("git@example.com:owner/repo.git"), "owner/repo");
+    }
+
+    #[test]
+    fn stable_ids_are_negative_and_repeatable() {
+        let a = stable_id("https://example.com/a.git");
+        let b = stable_id("https://example.com/b.git");
+        assert!(a < 0 && b < 0);
+        assert_eq!(a, stable_id("https://example.com/a.git"));
+        assert_ne!(a, b);
+    }
+}
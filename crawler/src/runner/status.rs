@@ -0,0 +1,214 @@
+use crate::config::Config;
+use crate::display;
+use crate::miner::FileProgress;
+use super::db::Database;
+use super::git::RepoData;
+
+use log::{error, info};
+use sqlx::{self, Any, Row};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the display is redrawn on a terminal.
+const DRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a summary is logged otherwise.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Width of the progress bar of each repo.
+const BAR_WIDTH: usize = 30;
+
+/// Progress of the crawl, shown while mining.
+///
+/// On a terminal, a summary & a progress bar for each repo are kept at the
+/// bottom of stderr. Otherwise, a summary is logged periodically.
+pub struct Status {
+    threads: usize,
+    total: usize,               // Repos left to mine when the crawl started
+    start: Instant,
+    history: (usize, f64),      // Number & total time in ms of repos in `stats`
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    active: BTreeMap<i64, Active>,
+    done: usize,
+    n_files: usize,             // Files tried in finished repos
+    n_success: usize,
+    time: Duration,             // Time spent on finished repos
+}
+
+/// A repo that has been claimed but not finished.
+struct Active {
+    name: String,
+    progress: Arc<FileProgress>,
+    start: Instant,
+}
+
+impl Status {
+    pub fn new(config: &Config, db: &Database) -> Self {
+        let (total, history) = match db.rt.block_on(Self::counts(db)) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Failed to count repositories: {:?}", e);
+                (0, (0, 0.0))
+            },
+        };
+
+        return Self {
+            threads: config.runner.threads,
+            total,
+            start: Instant::now(),
+            history,
+            state: Default::default(),
+        };
+    }
+
+    /// Start showing the PROGRESS of REPO.
+    pub fn begin(&self, repo: &RepoData, progress: Arc<FileProgress>) {
+        let active = Active { name: repo.name.clone(), progress, start: Instant::now() };
+        self.state.lock().unwrap().active.insert(repo.id, active);
+    }
+
+    /// Count the repo with ID as done.
+    pub fn finish(&self, id: i64) {
+        let mut state = self.state.lock().unwrap();
+        let Some(active) = state.active.remove(&id) else {
+            return;
+        };
+
+        state.done += 1;
+        state.n_files += active.progress.done.load(Ordering::Relaxed);
+        state.n_success += active.progress.success.load(Ordering::Relaxed);
+        state.time += active.start.elapsed();
+    }
+
+    /// Show the progress until STOP is closed.
+    pub fn run_until(&self, stop: Receiver<()>) {
+        let terminal = display::is_terminal();
+        let interval = match terminal {
+            true => DRAW_INTERVAL,
+            false => SUMMARY_INTERVAL,
+        };
+
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            match terminal {
+                true => display::draw(self.lines()),
+                false => info!("{}", self.summary()),
+            }
+        }
+
+        if terminal {
+            display::clear();
+        }
+        info!("{}", self.summary());
+    }
+
+    /// Return the summary line, followed by a line for each active repo.
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.summary()];
+
+        let state = self.state.lock().unwrap();
+        let width = state.active.values().map(|a| a.name.len()).max().unwrap_or(0);
+        for active in state.active.values() {
+            let total = active.progress.total.load(Ordering::Relaxed);
+            let done = active.progress.done.load(Ordering::Relaxed);
+            let time = format_duration(active.start.elapsed());
+            let line = match total {
+                0 => format!("  {:<width$}  preparing  {}", active.name, time),
+                _ => format!(
+                    "  {:<width$}  [{}]  {}/{} files  {}",
+                    active.name, bar(done, total), done, total, time
+                ),
+            };
+            lines.push(line);
+        }
+
+        return lines;
+    }
+
+    /// Return a line summarizing the progress of the whole crawl.
+    fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let elapsed = self.start.elapsed();
+
+        // Include the files of repos still being mined
+        let mut n_files = state.n_files;
+        let mut n_success = state.n_success;
+        for active in state.active.values() {
+            n_files += active.progress.done.load(Ordering::Relaxed);
+            n_success += active.progress.success.load(Ordering::Relaxed);
+        }
+
+        let rate = match n_files {
+            0 => "-".to_string(),
+            n => format!("{:.1}%", 100.0 * n_success as f64 / n as f64),
+        };
+        let repos_per_hour = state.done as f64 / elapsed.as_secs_f64().max(1.0) * 3600.0;
+        let files_per_sec = n_files as f64 / elapsed.as_secs_f64().max(1.0);
+
+        return format!(
+            "Repos: {}/{} done, {} in flight | Files: {}, {} compiled | {:.1} repos/h, {:.1} files/s | ETA: {}",
+            state.done, self.total, state.active.len(),
+            n_files, rate,
+            repos_per_hour, files_per_sec,
+            self.eta(&state).map_or("unknown".to_string(), format_duration),
+        );
+    }
+
+    /// Estimate the time left, from the average time of the repos mined by
+    /// this & previous crawls.
+    fn eta(&self, state: &State) -> Option<Duration> {
+        let (n, time) = self.history;
+        let n = n + state.done;
+        if n == 0 {
+            return None;
+        }
+
+        let average = (time / 1000.0 + state.time.as_secs_f64()) / n as f64;
+        let left = self.total.saturating_sub(state.done + state.active.len()) as f64;
+        let secs = average * left / self.threads.max(1) as f64;
+        return Some(Duration::from_secs_f64(secs));
+    }
+
+    /// Return the number of repos left to mine, & the number & total time of
+    /// repos that have been mined.
+    async fn counts(db: &Database) -> Result<(usize, (usize, f64)), sqlx::Error> {
+        let row = sqlx::query::<Any>(
+            "select count(*)
+             from repos
+             where repo_id not in (select repo_id from mined)
+               and repo_id not in (select repo_id from skipped)"
+        ).fetch_one(&db.pool)
+            .await?;
+        let left = row.try_get::<i64, usize>(0)?;
+
+        let row = sqlx::query::<Any>("select count(*), coalesce(sum(time), 0e0) from stats")
+            .fetch_one(&db.pool)
+            .await?;
+        let n = row.try_get::<i64, usize>(0)?;
+        let time = row.try_get::<f64, usize>(1)?;
+
+        return Ok((left as usize, (n as usize, time)));
+    }
+}
+
+/// Return a bar that is DONE / TOTAL full.
+fn bar(done: usize, total: usize) -> String {
+    let full = (BAR_WIDTH * done.min(total)) / total.max(1);
+    return format!("{}{}", "#".repeat(full), "-".repeat(BAR_WIDTH - full));
+}
+
+/// Format D as hours, minutes & seconds.
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::config::Submodules;
+use super::git::{git, git_timeout, FetchError, TIMED_OUT};
+
+use log::{debug, info, warn};
+
+/// Initialize the submodules of the repository in DIR allowed by CONFIG, &
+/// return their paths relative to DIR.
+///
+/// Only the commit recorded by the host repository is fetched. Each fetch is
+/// killed after TIMEOUT seconds, & the repository is given up on, as the
+/// submodule's server would most likely time out again.
+pub fn init(dir: &PathBuf, config: &Submodules, timeout: Option<u64>) -> Result<Vec<PathBuf>, FetchError> {
+    let allowed: Vec<String> = declared(dir)
+        .into_iter()
+        .filter(|(path, url)| {
+            let ok = config.allow.is_empty()
+                || config.allow.iter().any(|prefix| url.starts_with(prefix));
+            if !ok {
+                debug!("Submodule '{}' at '{}' is not allowed", url, path);
+            }
+            ok
+        })
+        .map(|(path, _)| path)
+        .collect();
+
+    if allowed.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Sparse checkouts skip anything that doesn't match their patterns
+    let sparse = git(Some(dir), &["config", "--get", "core.sparseCheckout"]);
+    if sparse.as_deref() == Ok("true") {
+        let mut args = vec!["sparse-checkout", "add"];
+        let dirs: Vec<String> = allowed.iter().map(|p| format!("/{}/", p)).collect();
+        args.extend(dirs.iter().map(|d| d.as_str()));
+        if let Err(e) = git(Some(dir), &args) {
+            warn!("Failed to add submodules to the sparse checkout: {}", e);
+        }
+    }
+
+    let mut acc = vec![];
+    for path in allowed {
+        let args = ["submodule", "update", "--init", "--depth", "1", "--", &path];
+        match git_timeout(Some(dir), &args, timeout) {
+            Ok(_) => {
+                info!("Initialized submodule '{}' in {:?}", path, dir);
+                acc.push(PathBuf::from(path));
+            },
+            Err(e) if e == TIMED_OUT => {
+                warn!("Timed out initializing submodule '{}'", path);
+                return Err(FetchError::Timeout(timeout.unwrap_or(0)));
+            },
+            Err(e) => warn!("Failed to initialize submodule '{}': {}", path, e),
+        }
+    }
+
+    return Ok(acc);
+}
+
+/// Return the path & URL of each submodule declared by the checked out
+/// commit in DIR.
+fn declared(dir: &PathBuf) -> Vec<(String, String)> {
+    // Read from the commit, as .gitmodules might not be checked out
+    let out = git(Some(dir), &[
+        "config", "--blob", "HEAD:.gitmodules",
+        "--get-regexp", r"^submodule\..*\.(path|url)$",
+    ]);
+    let Ok(out) = out else {
+        return vec![];
+    };
+
+    // Each line is `submodule.NAME.KEY VALUE`
+    let mut paths: HashMap<&str, &str> = HashMap::new();
+    let mut urls: HashMap<&str, &str> = HashMap::new();
+    for line in out.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let key = key.trim_start_matches("submodule.");
+        if let Some(name) = key.strip_suffix(".path") {
+            paths.insert(name, value);
+        } else if let Some(name) = key.strip_suffix(".url") {
+            urls.insert(name, value);
+        }
+    }
+
+    let mut acc: Vec<(String, String)> = paths.into_iter()
+        .filter_map(|(name, path)| {
+            let url = urls.get(name)?;
+            Some((path.to_string(), url.to_string()))
+        })
+        .collect();
+    acc.sort();
+
+    return acc;
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::metrics;
+use super::github::{from_header, parse_time};
+
+use reqwest::header::HeaderMap;
+use log::{debug, info, warn};
+
+/// Environment variable containing a comma or whitespace separated token list.
+static TOKENS_ENV: &str = "GITHUB_TOKENS";
+
+/// A single API token & what we know about its rate limit.
+struct Token {
+    value: Option<String>,      // None for unauthenticated requests
+    remaining: Option<usize>,   // None until we have seen a response
+    reset: u64,                 // Unix time the quota resets
+    available_at: u64,          // Unix time the token can be used again
+}
+
+/// Never print the token itself.
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("value", &"<redacted>")
+            .field("remaining", &self.remaining)
+            .field("reset", &self.reset)
+            .field("available_at", &self.available_at)
+            .finish()
+    }
+}
+
+impl Token {
+    fn new(value: Option<String>) -> Self {
+        return Self { value, remaining: None, reset: 0, available_at: 0 };
+    }
+
+    /// Return the time at which this token can next be used.
+    fn ready_at(&self) -> u64 {
+        match self.remaining {
+            Some(0) => self.available_at.max(self.reset),
+            _ => self.available_at,
+        }
+    }
+}
+
+/// Set of API tokens. Requests use the token with the most remaining quota.
+pub struct TokenPool {
+    tokens: Mutex<Vec<Token>>,
+}
+
+impl TokenPool {
+    /// Collect the tokens from the config, the environment, & the token file.
+    pub fn new(config: &Config) -> Self {
+        let mut values: Vec<String> = vec![];
+
+        values.push(config.runner.github_api_key.clone());
+        values.extend(config.runner.github_api_keys.iter().cloned());
+
+        if let Ok(var) = env::var(TOKENS_ENV) {
+            values.extend(
+                var.split(|c: char| c == ',' || c.is_whitespace())
+                   .map(|s| s.to_string())
+            );
+        }
+
+        if let Some(path) = &config.runner.github_token_file {
+            match fs::read_to_string(path) {
+                Ok(s) => {
+                    values.extend(
+                        s.lines()
+                         .map(|l| l.trim())
+                         .filter(|l| !l.starts_with('#'))
+                         .map(|l| l.to_string())
+                    );
+                },
+                Err(e) => panic!("Unable to read token file {:?}: {}", path, e),
+            }
+        }
+
+        // Remove empty & duplicate tokens
+        let mut seen = HashSet::new();
+        let mut tokens = vec![];
+        for value in values {
+            let value = value.trim().to_string();
+            if value.is_empty() || seen.contains(&value) {
+                continue;
+            }
+            seen.insert(value.clone());
+            tokens.push(Token::new(Some(value)));
+        }
+
+        if tokens.is_empty() {
+            warn!("No GitHub API tokens, using unauthenticated requests");
+            tokens.push(Token::new(None));
+        }
+        info!("Using {} GitHub API tokens", tokens.len());
+
+        return Self { tokens: Mutex::new(tokens) };
+    }
+
+    /// Return the index & value of the token with the most remaining quota,
+    /// sleeping if every token is exhausted.
+    pub fn acquire(&self) -> (usize, Option<String>) {
+        loop {
+            let wait = {
+                let tokens = self.tokens.lock().unwrap();
+                let now = now();
+
+                // Unknown quota is assumed to be full
+                let best = tokens.iter()
+                    .enumerate()
+                    .filter(|(_, t)| t.ready_at() <= now)
+                    .max_by_key(|(_, t)| t.remaining.unwrap_or(usize::MAX));
+
+                if let Some((i, t)) = best {
+                    debug!("Using token {}: {:?}", i, t);
+                    return (i, t.value.clone());
+                }
+
+                // Every token is exhausted, wait for the first to reset
+                let next = tokens.iter().map(|t| t.ready_at()).min().unwrap();
+                next.saturating_sub(now) + 1
+            };
+
+            info!("All tokens exhausted, sleeping for {} seconds", wait);
+            thread::sleep(Duration::from_secs(wait));
+        }
+    }
+
+    /// Update the quota of token INDEX from the response HEADERS.
+    pub fn update(&self, index: usize, headers: &HeaderMap) {
+        let mut tokens = self.tokens.lock().unwrap();
+        let token = &mut tokens[index];
+
+        if let Some(remaining) = from_header::<usize>(headers, "x-ratelimit-remaining") {
+            token.remaining = Some(remaining);
+        }
+        if let Some(reset) = from_header::<u64>(headers, "x-ratelimit-reset") {
+            token.reset = reset;
+        }
+
+        let remaining: usize = tokens.iter().filter_map(|t| t.remaining).sum();
+        metrics::RATE_LIMIT_REMAINING.set(remaining as i64);
+    }
+
+    /// Don't use token INDEX for SECONDS.
+    pub fn suspend(&self, index: usize, seconds: f64) {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens[index].available_at = now() + seconds.ceil() as u64;
+        info!("Suspending token {} for {} seconds", index, seconds);
+    }
+
+    /// Return the number of tokens.
+    pub fn len(&self) -> usize {
+        return self.tokens.lock().unwrap().len();
+    }
+
+    /// Return true if token INDEX has no remaining quota.
+    pub fn exhausted(&self, index: usize) -> bool {
+        let tokens = self.tokens.lock().unwrap();
+        return tokens[index].remaining == Some(0);
+    }
+}
+
+fn now() -> u64 {
+    return parse_time(SystemTime::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+    use std::time::Instant;
+
+    fn pool(n: usize) -> TokenPool {
+        let tokens = (0..n).map(|i| Token::new(Some(format!("token-{}", i)))).collect();
+        return TokenPool { tokens: Mutex::new(tokens) };
+    }
+
+    fn headers(remaining: usize, reset: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(reset));
+        return headers;
+    }
+
+    #[test]
+    fn rotate_to_most_remaining() {
+        let pool = pool(2);
+        let reset = now() + 3600;
+
+        // Tokens that haven't been used are assumed to be full
+        pool.update(1, &headers(10, reset));
+        assert_eq!(pool.acquire(), (0, Some("token-0".to_string())));
+
+        pool.update(0, &headers(5, reset));
+        assert_eq!(pool.acquire().0, 1);
+
+        pool.update(1, &headers(0, reset));
+        assert!(pool.exhausted(1));
+        assert_eq!(pool.acquire().0, 0);
+    }
+
+    #[test]
+    fn skip_suspended_token() {
+        let pool = pool(2);
+        let reset = now() + 3600;
+        pool.update(0, &headers(5, reset));
+        pool.update(1, &headers(10, reset));
+
+        pool.suspend(1, 60.0);
+        assert_eq!(pool.acquire().0, 0);
+        assert!(!pool.exhausted(1));
+    }
+
+    #[test]
+    fn sleep_until_reset_when_exhausted() {
+        let pool = pool(2);
+        let reset = now() + 1;
+        pool.update(0, &headers(0, reset));
+        pool.update(1, &headers(0, reset + 60));
+
+        // The first token to reset is used once it does
+        let start = Instant::now();
+        assert_eq!(pool.acquire().0, 0);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+}
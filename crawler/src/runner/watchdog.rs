@@ -0,0 +1,89 @@
+use crate::config::Config;
+use crate::miner::FileProgress;
+use super::git::RepoData;
+
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the repos being mined are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Logs repos that have stopped making progress while being mined.
+pub struct Watchdog {
+    stall_time: Duration,
+    repos: Mutex<HashMap<i64, Watched>>,
+}
+
+/// A repo being mined.
+struct Watched {
+    name: String,
+    progress: Arc<FileProgress>,    // Updated by the miner
+    seen: usize,                    // Files done at the last change
+    since: Instant,                 // Time of the last change
+    stalled: bool,                  // Whether a warning has been logged
+}
+
+impl Watchdog {
+    pub fn new(config: &Config) -> Self {
+        return Self {
+            stall_time: Duration::from_secs(config.runner.stall_time),
+            repos: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Start watching the PROGRESS of mining REPO.
+    pub fn watch(&self, repo: &RepoData, progress: Arc<FileProgress>) {
+        self.repos.lock().unwrap().insert(repo.id, Watched {
+            name: repo.name.clone(),
+            seen: progress.done.load(Ordering::Relaxed),
+            progress,
+            since: Instant::now(),
+            stalled: false,
+        });
+    }
+
+    /// Stop watching the repo with ID.
+    pub fn unwatch(&self, id: i64) {
+        self.repos.lock().unwrap().remove(&id);
+    }
+
+    /// Check the watched repos periodically, until STOP is closed.
+    pub fn run_until(&self, stop: Receiver<()>) {
+        loop {
+            match stop.recv_timeout(CHECK_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => self.check(),
+                _ => return,
+            }
+        }
+    }
+
+    /// Log each repo that hasn't compiled a file within the stall time.
+    fn check(&self) {
+        let mut repos = self.repos.lock().unwrap();
+        for watched in repos.values_mut() {
+            let progress = watched.progress.done.load(Ordering::Relaxed);
+            if progress != watched.seen {
+                if watched.stalled {
+                    info!("Mining of '{}' is progressing again", watched.name);
+                }
+                watched.seen = progress;
+                watched.since = Instant::now();
+                watched.stalled = false;
+                continue;
+            }
+
+            let idle = watched.since.elapsed();
+            if !watched.stalled && idle >= self.stall_time {
+                warn!(
+                    "No progress mining '{}' for {} s, after {} files",
+                    watched.name, idle.as_secs(), progress
+                );
+                watched.stalled = true;
+            }
+        }
+    }
+}
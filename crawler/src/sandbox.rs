@@ -0,0 +1,172 @@
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// A Linux namespace sandbox for a single process, prepared before forking so
+/// nothing has to be allocated in the child.
+///
+/// The process gets its own user, mount, & network namespaces. It can't reach
+/// the network, has a private `/tmp`, & the repository it is compiling is
+/// mounted read-only. The rest of the filesystem is still accessible with the
+/// user's permissions, including other repositories when `tmp_dir` is outside
+/// `/tmp`. No privileges are needed, as long as the kernel allows unprivileged
+/// user namespaces.
+pub struct Sandbox {
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    root: Option<Root>,
+}
+
+/// The repository, which is mounted read-only.
+struct Root {
+    dir: File,              // Reserves a descriptor for the repository in the child
+    source: CString,        // /proc/self/fd/N of that descriptor
+    target: CString,
+    mkdirs: Vec<CString>,   // Directories to create in the private /tmp
+    flags: libc::c_ulong,   // Flags of the original mount, which must be kept
+}
+
+impl Sandbox {
+    /// Prepare a sandbox that can read ROOT, if given.
+    pub fn new(root: Option<&PathBuf>) -> io::Result<Self> {
+        let uid = unsafe { libc::geteuid() };
+        let gid = unsafe { libc::getegid() };
+
+        let root = match root {
+            Some(root) => Some(Root::new(root)?),
+            None => None,
+        };
+
+        return Ok(Self {
+            uid_map: format!("{} {} 1", uid, uid).into_bytes(),
+            gid_map: format!("{} {} 1", gid, gid).into_bytes(),
+            root,
+        });
+    }
+
+    /// Enter the sandbox. Only called in the child between fork & exec, so
+    /// it may only make system calls.
+    pub fn enter(&self) -> io::Result<()> {
+        let flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET;
+        check(unsafe { libc::unshare(flags) })?;
+
+        // Keep the same user & group inside the namespace
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", &self.uid_map)?;
+        write_file(c"/proc/self/gid_map", &self.gid_map)?;
+
+        // Don't let mounts leak out of the namespace
+        mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE)?;
+
+        // Open the repository again in the new namespace, as only its mounts
+        // can be bind mounted, so it can still be found once /tmp is hidden
+        if let Some(root) = &self.root {
+            let flags = libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC;
+            let fd = unsafe { libc::open(root.target.as_ptr(), flags) };
+            check(fd)?;
+            let ret = unsafe { libc::dup3(fd, root.dir.as_raw_fd(), libc::O_CLOEXEC) };
+            unsafe { libc::close(fd) };
+            check(ret)?;
+        }
+
+        mount(Some(c"tmpfs"), c"/tmp", Some(c"tmpfs"), libc::MS_NOSUID | libc::MS_NODEV)?;
+
+        if let Some(root) = &self.root {
+            for dir in &root.mkdirs {
+                let ret = unsafe { libc::mkdir(dir.as_ptr(), 0o755) };
+                if ret != 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            let target = root.target.as_c_str();
+            mount(Some(&root.source), target, None, libc::MS_BIND | libc::MS_REC)?;
+            let ro = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | root.flags;
+            mount(None, target, None, ro)?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl Root {
+    fn new(path: &Path) -> io::Result<Self> {
+        let path = path.canonicalize()?;
+        let dir = File::open(&path)?;
+        let source = CString::new(format!("/proc/self/fd/{}", dir.as_raw_fd()))?;
+        let target = CString::new(path.as_os_str().as_bytes())?;
+
+        // The repository is hidden by the private /tmp if it is inside it
+        let mut mkdirs = vec![];
+        if path.starts_with("/tmp") {
+            for dir in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+                if dir.starts_with("/tmp") && dir != Path::new("/tmp") {
+                    mkdirs.push(CString::new(dir.as_os_str().as_bytes())?);
+                }
+            }
+        }
+
+        // A read-only remount in a user namespace must keep these flags
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::statvfs(target.as_ptr(), &mut stat) })?;
+        let mut flags = 0;
+        for (st, ms) in [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if stat.f_flag & st != 0 {
+                flags |= ms;
+            }
+        }
+
+        return Ok(Self { dir, source, target, mkdirs, flags });
+    }
+}
+
+/// Return the last OS error if RET is negative.
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/// Write DATA to the file at PATH, without allocating.
+fn write_file(path: &CStr, data: &[u8]) -> io::Result<()> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    check(fd)?;
+
+    let ret = unsafe { libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) };
+    unsafe { libc::close(fd) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/// Mount SOURCE at TARGET, without allocating.
+fn mount(
+    source: Option<&CStr>,
+    target: &CStr,
+    kind: Option<&CStr>,
+    flags: libc::c_ulong,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::mount(
+            source.map_or(ptr::null(), |s| s.as_ptr()),
+            target.as_ptr(),
+            kind.map_or(ptr::null(), |k| k.as_ptr()),
+            flags,
+            ptr::null(),
+        )
+    };
+    return check(ret);
+}
@@ -7,6 +7,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 use crawler::interface::{CompileInput, CompileResult};
+use crawler::process;
 use crate::data::{KernelMatch, Match};
 
 const BIN: &str = "/home/nju/.opt/KernelFaRer/build/install/bin";
@@ -20,12 +21,12 @@ lazy_static! {
 }
 
 /// Return a compilation error.
-fn compile_fail(log: &mut String) -> CompileResult {
-    CompileResult { data: Err(()), to_log: log.to_string() }
+fn compile_fail() -> CompileResult {
+    CompileResult { data: Err(()), to_log: String::new() }
 }
 
 /// Get the path of a binary in the provied LLVM directory.
-fn get_compile_bin(bin: &str) -> PathBuf {
+pub fn get_compile_bin(bin: &str) -> PathBuf {
     let dir = PathBuf::from_str(BIN).unwrap();
     return dir.join(bin);
 }
@@ -37,32 +38,27 @@ fn format_headers(headers: &Vec<PathBuf>) -> Vec<String> {
            .collect()
 }
 
-fn compile_file(input: &CompileInput, log: &mut String) -> Result<String, ()> {
+fn compile_file(input: &CompileInput) -> Result<String, ()> {
     // Get the path to clang from the args
     let clang = get_compile_bin("clang");
     let headers = format_headers(input.headers);
 
     // Run a quick compilation so we can check for errors
-    let compile = Command::new("timeout")
-        .arg("10")
-        .arg(clang)
-        .args(["-g", "-O3", "-emit-llvm", "-S"])
-        .args(["-mllvm", "--enable-kernel-replacer"])
-        .args(["-mllvm", "--gemmfarer-replacement-mode=cblas-interface"])
-        .args(["-mllvm", "--debug-only=gemm-replacer-pass"])
-        .args(["-o", "-"])
-        .arg(input.file)
-        .args(headers)
-        .output()
-        .unwrap();
-
-    // Log the command used
-    log.push_str("\n==============================\n");
-    log.push_str(
-        &format!("Try for file {:?}:\nHeaders: {:?}", input.file, input.headers)
-    );
-
-    // Try to get the output
+    let compile = process::output(
+        Command::new("timeout")
+            .arg("10")
+            .arg(clang)
+            .args(["-g", "-O3", "-emit-llvm", "-S"])
+            .args(["-mllvm", "--enable-kernel-replacer"])
+            .args(["-mllvm", "--gemmfarer-replacement-mode=cblas-interface"])
+            .args(["-mllvm", "--debug-only=gemm-replacer-pass"])
+            .args(["-o", "-"])
+            .arg(input.file)
+            .args(headers)
+    ).unwrap();
+
+    // Get the debug output of the pass. The command & its output are added
+    // to the log by the miner.
     let output = match String::from_utf8(compile.stderr) {
         Ok(s) => s,
         Err(e) => {
@@ -70,22 +66,6 @@ fn compile_file(input: &CompileInput, log: &mut String) -> Result<String, ()> {
             "".to_string()
         }
     };
-    log.push_str("\nOutput:\n");
-    log.push_str("------------------------------\n");
-    log.push_str(&output);
-    log.push_str("------------------------------\n");
-
-    // If the compilation timed out, print so
-    if let Some(code) = compile.status.code() {
-        if code == 124 {
-            log.push_str("timed out\n");
-        }
-    }
-    if compile.status.success() {
-        log.push_str("success\n")
-    } else {
-        log.push_str("failed\n")
-    }
 
     return Ok(output);
 }
@@ -103,15 +83,15 @@ fn parse_output(input: &str) -> Vec<KernelMatch> {
 }
 
 /// Try to compile a file, & return the match data if successful.
-pub fn try_compile(input: &CompileInput, log: &mut String) -> CompileResult {
+pub fn try_compile(input: &CompileInput) -> CompileResult {
     // Compile the file
-    let out = match compile_file(input, log) {
+    let out = match compile_file(input) {
         Ok(src) => src,
-        Err(_) => return compile_fail(log),
+        Err(_) => return compile_fail(),
     };
 
     // Parse the results
     let data = parse_output(&out);
     let m = Match { file: input.file.into(), data};
-    CompileResult { data: Ok(Box::new(m)), to_log: log.to_string() }
+    CompileResult { data: Ok(Box::new(m)), to_log: String::new() }
 }
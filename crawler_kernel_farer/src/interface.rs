@@ -1,27 +1,49 @@
 use crawler::interface::{
     InitInput, InitResult, CompileInput, CompileResult, Interface,
-    InternInput, InternResult, PreInput, PreprocessResult
+    InternInput, InternResult, PreInput, PreprocessResult, Provenance
 };
 
 use log::error;
 
-use crate::{compile::try_compile, intern::intern_matches};
+use crate::{compile::{get_compile_bin, try_compile}, intern::intern_matches};
 
 pub struct KernelFaRer {}
 
 impl Interface for KernelFaRer {
+    /// Record the KernelFaRer build of clang, which includes the pass.
+    fn provenance(&self) -> Provenance {
+        return Provenance {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            tools: vec![get_compile_bin("clang")],
+            plugins: vec![],
+        };
+    }
+
     /// Create new tables to store the files & matches.
     fn init(&self, input: InitInput) -> InitResult {
         let result: Result<(), sqlx::Error> = input.db.rt.block_on(async {
             let _ = sqlx::query(
                 "create table if not exists files (
                  file_id     bigint,
-                 repo_id     int,
+                 repo_id     bigint,
+                 commit_sha  varchar(40),
                  path        text,
+                 run_id      int,
                  primary key (file_id),
                  foreign key (repo_id) references repos)")
                 .execute(&input.db.pool).await?;
 
+            // Added for historical mining
+            let _ = sqlx::query(
+                "alter table files add column if not exists commit_sha varchar(40)")
+                .execute(&input.db.pool).await?;
+
+            // Added to trace results back to the run that produced them
+            let _ = sqlx::query(
+                "alter table files add column if not exists run_id int")
+                .execute(&input.db.pool).await?;
+
             let _ = sqlx::query(
                 "create table if not exists matches (
                  match_id    bigint,
@@ -48,9 +70,7 @@ impl Interface for KernelFaRer {
 
     /// Compile a single file using SI cost model.
     fn compile(&self, input: CompileInput) -> CompileResult {
-        // Log output
-        let mut log = "".to_string();
-        try_compile(&input, &mut log)
+        try_compile(&input)
     }
 
     fn intern(&self, input: InternInput) -> InternResult {
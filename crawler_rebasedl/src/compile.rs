@@ -1,22 +1,24 @@
-use log::error;
-
-use std::process::{Command, Stdio};
-use std::io::{Error, Write};
+use std::process::Command;
+use std::io::Error;
 use std::str::FromStr;
 use std::path::PathBuf;
 
 use crawler::interface::{CompileInput, CompileResult};
+use crawler::process;
 
 use crate::data::Match;
 use crate::output_parser;
 
+/// Path of the RebaseDL pass plugin.
+pub const PASS: &str = "/home/nju/.opt/rebasedl-pass/build/lib/libRebaseDLPass.so";
+
 /// Return a compilation error.
-fn compile_fail(log: &mut String) -> CompileResult {
-    CompileResult { data: Err(()), to_log: log.to_string() }
+fn compile_fail() -> CompileResult {
+    CompileResult { data: Err(()), to_log: String::new() }
 }
 
 /// Get the path of a binary in the provied LLVM directory.
-fn get_compile_bin(bin: &str) -> PathBuf {
+pub fn get_compile_bin(bin: &str) -> PathBuf {
     let dir = PathBuf::from_str("/home/nju/.opt/llvm-17/llvm-bin/bin").unwrap();
     return dir.join(bin);
 }
@@ -28,85 +30,44 @@ fn format_headers(headers: &Vec<PathBuf>) -> Vec<String> {
            .collect()
 }
 
-fn compile_file(input: &CompileInput, log: &mut String) -> Result<Vec<u8>, ()> {
+fn compile_file(input: &CompileInput) -> Result<Vec<u8>, ()> {
     // Get the path to clang from the args
     let clang = get_compile_bin("clang");
     let headers = format_headers(input.headers);
 
     // Run a quick compilation so we can check for errors
-    let compile = Command::new("timeout")
-        .arg("10")
-        .arg(clang)
-        .args(["-g", "-O3", "-fno-unroll-loops", "-emit-llvm", "-S"])
-        .args(["-o", "-"])
-        .arg(input.file)
-        .args(headers)
-        .output()
-        .unwrap();
-
-    // Log the command used
-    log.push_str("\n==============================\n");
-    log.push_str(
-        &format!("Try for file {:?}:\nHeaders: {:?}", input.file, input.headers)
-    );
-
-    // Try to get the output
-    let output = match String::from_utf8(compile.stderr) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("Failed to read compilation output for: {:?}", e);
-            "".to_string()
-        }
-    };
-    log.push_str("\nOutput:\n");
-    log.push_str("------------------------------\n");
-    log.push_str(&output);
-    log.push_str("------------------------------\n");
-
-    // If the compilation timed out, print so
-    if let Some(code) = compile.status.code() {
-        if code == 124 {
-            log.push_str("timed out\n");
-        }
-    }
-
-    // Return true if the compilation succeeded
+    let compile = process::output(
+        Command::new("timeout")
+            .arg("10")
+            .arg(clang)
+            .args(["-g", "-O3", "-fno-unroll-loops", "-emit-llvm", "-S"])
+            .args(["-o", "-"])
+            .arg(input.file)
+            .args(headers)
+    ).unwrap();
+
+    // The command & its output are added to the log by the miner
+    // Return the IR if the compilation succeeded
     let result = match compile.status.success() {
         true => Ok(compile.stdout),
         false => Err(()),
     };
 
-    if result.is_ok() {
-        log.push_str("success\n")
-    } else {
-        log.push_str("failed\n")
-    }
-
     return result;
 }
 
 /// Run the RebaseDL pass on input LLVM IR.
 fn run_rebasedl_pass(src: &[u8]) -> Result<String, ()> {
     let opt = get_compile_bin("opt");
-    let pass = "/home/nju/.opt/rebasedl-pass/build/lib/libRebaseDLPass.so";
-
-    let mut cmd = Command::new(opt)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .arg("-disable-output")
-        .arg(&format!("-load-pass-plugin={}", pass))
-        .arg("-passes=rebasedl")
-        .spawn()
-        .unwrap();
-
-    // Send the source file
-    let mut stdin = cmd.stdin.take().unwrap();
-    stdin.write_all(src).unwrap();
-    drop(stdin);
-
-    // Get the output
-    let output = cmd.wait_with_output().unwrap();
+
+    // Send the source file & get the output
+    let output = process::output_with_input(
+        Command::new(opt)
+            .arg("-disable-output")
+            .arg(&format!("-load-pass-plugin={}", PASS))
+            .arg("-passes=rebasedl"),
+        src
+    ).unwrap();
     let out = String::from_utf8(output.stderr)
         .expect("Failed to parse pass output");
 
@@ -114,21 +75,21 @@ fn run_rebasedl_pass(src: &[u8]) -> Result<String, ()> {
 }
 
 /// Try to compile a file, & return the match data if successful.
-pub fn try_compile(input: &CompileInput, log: &mut String) -> CompileResult {
+pub fn try_compile(input: &CompileInput) -> CompileResult {
     // Compile the file
-    let src = match compile_file(input, log) {
+    let src = match compile_file(input) {
         Ok(src) => src,
-        Err(_) => return compile_fail(log),
+        Err(_) => return compile_fail(),
     };
 
     // Run the RebaseDL pass
     let pass_output = match run_rebasedl_pass(&src) {
         Ok(out) => out,
-        Err(_) => return compile_fail(log),
+        Err(_) => return compile_fail(),
     };
 
     // Parse the results
     let data = output_parser::parse(pass_output);
     let m = Match { file: input.file.into(), data};
-    CompileResult { data: Ok(Box::new(m)), to_log: log.to_string() }
+    CompileResult { data: Ok(Box::new(m)), to_log: String::new() }
 }
@@ -1,9 +1,9 @@
 use crawler::interface::{CompileInput, CompileResult, MatchData};
+use crawler::process;
 use crate::data::Match;
 use crate::loops::Loops;
 
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::process::Command;
 use std::path::PathBuf;
 use std::str::FromStr;
 use log::error;
@@ -22,65 +22,34 @@ fn format_headers(headers: &Vec<PathBuf>) -> Vec<String> {
 }
 
 /// Return true if the compilation succeeded, & return the output.
-pub fn try_compile(input: &CompileInput, log: &mut String) -> Result<Vec<u8>, ()> {
+pub fn try_compile(input: &CompileInput) -> Result<Vec<u8>, ()> {
     // Get the path to clang from the args
     let clang = get_compile_bin("clang");
     let headers = format_headers(input.headers);
 
     // Run a quick compilation so we can check for errors
-    let compile = Command::new("timeout")
-        .arg("5")
-        .arg(clang)
-        .arg("-c")
-        .arg(input.file)
-        .args(headers)
-        .args(["-emit-llvm", "-g", "-o", "-",])
-        .output()
-        .unwrap();
-
-    // Log the command used
-    log.push_str("\n==============================\n");
-    log.push_str(
-        &format!("Try for file {:?}:\nHeaders: {:?}", input.file, input.headers)
-    );
-
-    // Try to get the output
-    let output = match String::from_utf8(compile.stderr) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("Failed to read compilation output for: {:?}", e);
-            "".to_string()
-        }
-    };
-    log.push_str("\nOutput:\n");
-    log.push_str("------------------------------\n");
-    log.push_str(&output);
-    log.push_str("------------------------------\n");
-
-    // If the compilation timed out, print so
-    if let Some(code) = compile.status.code() {
-        if code == 124 {
-            log.push_str("timed out\n");
-        }
-    }
-
-    // Return true if the compilation succeeded
+    let compile = process::output(
+        Command::new("timeout")
+            .arg("5")
+            .arg(clang)
+            .arg("-c")
+            .arg(input.file)
+            .args(headers)
+            .args(["-emit-llvm", "-g", "-o", "-",])
+    ).unwrap();
+
+    // Return the IR if the compilation succeeded. The command & its output
+    // are added to the log by the miner.
     let result = match compile.status.success() {
         true => Ok(compile.stdout),
         false => Err(()),
     };
 
-    if result.is_ok() {
-        log.push_str("success\n")
-    } else {
-        log.push_str("failed\n")
-    }
-
     return result;
 }
 
 /// Given a successful header combination, compile the file & find matches.
-pub fn find_match_data(input: &CompileInput, log: &mut String, src: &[u8]) -> CompileResult {
+pub fn find_match_data(input: &CompileInput, src: &[u8]) -> CompileResult {
     // Find the innermost loops in the file
     let mut loops = Loops::inner_loops(src);
 
@@ -89,17 +58,17 @@ pub fn find_match_data(input: &CompileInput, log: &mut String, src: &[u8]) -> Co
         Ok(s) => s,
         Err(e) => {
             error!("Failed to insert pragma: {:?}", e);
-            return CompileResult { data: Err(()), to_log: log.to_string() };
+            return CompileResult { data: Err(()), to_log: String::new() };
         },
     };
 
     // Compile with SI & find remarks
-    let Ok(output) = find_matches(input, pragma_src, log) else {
-        return CompileResult { data: Err(()), to_log: log.to_string() };
+    let Ok(output) = find_matches(input, pragma_src) else {
+        return CompileResult { data: Err(()), to_log: String::new() };
     };
 
     // Parse the remarks & debug info
-    loops.opt_info(&output, log);
+    loops.opt_info(&output);
 
     let result: MatchData = Box::new(Match {
         // Return the relative path
@@ -107,42 +76,26 @@ pub fn find_match_data(input: &CompileInput, log: &mut String, src: &[u8]) -> Co
         loops,
     });
 
-    return CompileResult { data: Ok(result), to_log: log.to_string() }
+    return CompileResult { data: Ok(result), to_log: String::new() }
 }
 
 /// Find the SI data for a given file.
-fn find_matches(input: &CompileInput, src: String, log: &mut String) -> Result<String, ()> {
+fn find_matches(input: &CompileInput, src: String) -> Result<String, ()> {
     let info_pass = env!("CRAWLER_SI_INFO");
-    let mut compile = Command::new("timeout")
+    let mut compile = Command::new("timeout");
+    compile
         .arg("10")
         .arg(get_compile_bin("clang"))
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
         .args(["-c", "-x", "c", "-g"])
         .args(format_headers(input.headers))
         .args(["-o", "-"])
         .args(["-emit-llvm", "-O3", "-Rpass=loop-vectorize"])
         .args(["-mllvm", "-debug-only=loop-vectorize"])
         .arg(&format!("-fpass-plugin={}", info_pass))
-        .arg("-")
-        .spawn()
-        .unwrap();
-
-    // Log the command used
-    log.push_str("\n==============================\n");
-    log.push_str(
-        &format!("Finding info for file {:?}:\nHeaders: {:?}",
-                 input.file, input.headers)
-    );
-
-    // Send the source file
-    let mut stdin = compile.stdin.take().unwrap();
-    stdin.write_all(src.as_bytes()).unwrap();
-    drop(stdin);
+        .arg("-");
 
-    // Get the compilation output
-    let out = compile.wait_with_output().unwrap();
+    // Send the source file & get the compilation output
+    let out = process::output_with_input(&mut compile, src.as_bytes()).unwrap();
 
     // Get the output as a string
     let output = match String::from_utf8(out.stderr) {
@@ -152,24 +105,12 @@ fn find_matches(input: &CompileInput, src: String, log: &mut String) -> Result<S
             return Err(());
         },
     };
-    log.push_str("\nOutput:\n");
-    log.push_str("------------------------------\n");
-    log.push_str(&output);
-    log.push_str("------------------------------\n");
 
     // If the compilation was successful, return the stderr
     if out.status.success() {
         return Ok(output);
     }
 
-    // If the compilation timed out, print so
-    if let Some(code) = out.status.code() {
-        if code == 124 {
-            log.push_str("timed out\n");
-        }
-    }
-
     // Failed, this shouldn't happen since we already tried to compile
-    log.push_str("failed\n");
     return Err(());
 }
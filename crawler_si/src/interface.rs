@@ -1,27 +1,53 @@
 use crawler::interface::{
     InitInput, InitResult, CompileInput, CompileResult, Interface,
-    InternInput, InternResult, PreInput, PreprocessResult
+    InternInput, InternResult, PreInput, PreprocessResult, Provenance
 };
-use crate::compile::{try_compile, find_match_data};
+use crate::compile::{get_compile_bin, try_compile, find_match_data};
 use crate::intern::intern_matches;
 
 use log::error;
+use std::path::PathBuf;
 
 pub struct FindVectorSI {}
 
 impl Interface for FindVectorSI {
+    /// Record the LLVM tools & the SI passes.
+    fn provenance(&self) -> Provenance {
+        return Provenance {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            tools: vec![get_compile_bin("clang"), get_compile_bin("opt")],
+            plugins: vec![
+                PathBuf::from(env!("CRAWLER_SI_INFO")),
+                PathBuf::from(env!("CRAWLER_SI_LOOPS")),
+            ],
+        };
+    }
+
     /// Create new tables to store the files & matches.
     fn init(&self, input: InitInput) -> InitResult {
         let result: Result<(), sqlx::Error> = input.db.rt.block_on(async {
             let _ = sqlx::query(
                 "create table if not exists files (
                  file_id     bigint,
-                 repo_id     int,
+                 repo_id     bigint,
+                 commit_sha  varchar(40),
                  path        text,
+                 run_id      int,
                  primary key (file_id),
                  foreign key (repo_id) references repos)")
                 .execute(&input.db.pool).await?;
 
+            // Added for historical mining
+            let _ = sqlx::query(
+                "alter table files add column if not exists commit_sha varchar(40)")
+                .execute(&input.db.pool).await?;
+
+            // Added to trace results back to the run that produced them
+            let _ = sqlx::query(
+                "alter table files add column if not exists run_id int")
+                .execute(&input.db.pool).await?;
+
             let _ = sqlx::query(
                 "create table if not exists matches (
                  match_id    bigint,
@@ -101,14 +127,11 @@ impl Interface for FindVectorSI {
 
     /// Compile a single file using SI cost model.
     fn compile(&self, input: CompileInput) -> CompileResult {
-        // Log output
-        let mut log = "".to_string();
-
         // Try to compile the file & return if it fails. Otherwise, find the
         // match data.
-        match try_compile(&input, &mut log) {
-            Err(_) => CompileResult { data: Err(()), to_log: log },
-            Ok(src) => find_match_data(&input, &mut log, &src),
+        match try_compile(&input) {
+            Err(_) => CompileResult { data: Err(()), to_log: String::new() },
+            Ok(src) => find_match_data(&input, &src),
         }
     }
 
@@ -16,7 +16,7 @@ pub fn intern_matches(conn: &mut Transaction<'_, Any>, input: InternInput) -> In
 
                 // Add the file to the files table
                 let file_id = input.db.rt.block_on(ensure_file(
-                    conn, &entry.file, input.repo_id
+                    conn, &entry.file, input.repo_id, input.commit, input.run_id
                 ));
                 let file_id = match file_id {
                     Ok(id) => id,
@@ -95,13 +95,14 @@ pub fn intern_matches(conn: &mut Transaction<'_, Any>, input: InternInput) -> In
     return Ok(());
 }
 
-/// Get the file_id of FILE.
-async fn file_id(pool: &mut Transaction<'_, Any>, file: &PathBuf, repo: i64) -> Option<i64> {
+/// Get the file_id of FILE at COMMIT.
+async fn file_id(pool: &mut Transaction<'_, Any>, file: &PathBuf, repo: i64, commit: Option<&str>) -> Option<i64> {
     let row = sqlx::query::<Any>(
         "select file_id
          from files
-         where repo_id = ? and path = ?"
+         where repo_id = ? and commit_sha <=> ? and path = ?"
     ).bind(repo)
+     .bind(commit)
      .bind(file.to_str())
      .fetch_one(pool.as_mut())
      .await;
@@ -113,22 +114,32 @@ async fn file_id(pool: &mut Transaction<'_, Any>, file: &PathBuf, repo: i64) ->
 }
 
 /// Ensure that the given file exists in the database.
-async fn ensure_file(conn: &mut Transaction<'_, Any>, file: &PathBuf, repo: i64) -> Result<i64, sqlx::Error> {
-    match file_id(conn, file, repo).await {
+async fn ensure_file(conn: &mut Transaction<'_, Any>, file: &PathBuf, repo: i64, commit: Option<&str>, run: i64) -> Result<i64, sqlx::Error> {
+    match file_id(conn, file, repo, commit).await {
         Some(id) => {
+            // The file now holds the results of this run
+            sqlx::query::<Any>("update files set run_id = ? where file_id = ?")
+                .bind(run)
+                .bind(id)
+                .execute(conn.as_mut())
+                .await?;
+
             Ok(id)
         }
         None => {
             // Insert the file
             sqlx::query::<Any>(
-                "insert into files values (uuid_short(), ?, ?)"
+                "insert into files (file_id, repo_id, commit_sha, path, run_id)
+                 values (uuid_short(), ?, ?, ?, ?)"
             )
                 .bind(repo)
+                .bind(commit)
                 .bind(file.to_str())
+                .bind(run)
                 .execute(conn.as_mut())
                 .await?;
 
-            return Ok(file_id(conn, file, repo).await.unwrap());
+            return Ok(file_id(conn, file, repo, commit).await.unwrap());
         }
     }
 }
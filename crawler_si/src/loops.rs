@@ -1,12 +1,13 @@
 use crate::compile::get_compile_bin;
+use crawler::process;
 use crate::data::{DebugInfo, Remark, SIStatus};
 use crate::pattern::{INFO_PATTERN, LOOP_PATTERN, MATCH_PATTERN, PRAGMA};
 
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::io::{Error, Write};
+use std::process::Command;
+use std::io::Error;
 use log::debug;
 use regex::Regex;
 
@@ -73,23 +74,14 @@ impl Loops {
         // Run the loop finder
         let loop_finder = env!("CRAWLER_SI_LOOPS");
         let opt = get_compile_bin("opt");
-        let mut find = Command::new(opt)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .arg(&format!("-load-pass-plugin={}", loop_finder))
-            .arg("-passes=print<inner-loop>")
-            .args(["-o", "/dev/null"])
-            .spawn()
-            .unwrap();
-
-        // Send the source file
-        let mut stdin = find.stdin.take().unwrap();
-        stdin.write_all(src).unwrap();
-        drop(stdin);
-
-        // Get the output
-        let output = find.wait_with_output().unwrap();
+        // Send the source file & get the output
+        let output = process::output_with_input(
+            Command::new(opt)
+                .arg(&format!("-load-pass-plugin={}", loop_finder))
+                .arg("-passes=print<inner-loop>")
+                .args(["-o", "/dev/null"]),
+            src
+        ).unwrap();
 
         // Parse the results
         let out = String::from_utf8(output.stderr)
@@ -147,7 +139,7 @@ impl Loops {
     }
 
     /// Collect all information after optimization has occured.
-    pub fn opt_info(&mut self, output: &str, _log: &mut String) {
+    pub fn opt_info(&mut self, output: &str) {
         // Collect the instruction counts for each loop
         let loop_info = parse_loop_info(output);
         debug!("{:?}", loop_info);